@@ -0,0 +1,24 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+const COMMANDS: &[&str] = &[
+    "load",
+    "execute",
+    "execute_batch",
+    "select",
+    "select_stream",
+    "ack_stream",
+    "cancel_stream",
+    "open_cursor",
+    "fetch_cursor",
+    "close_cursor",
+    "close",
+    "begin",
+    "commit",
+    "rollback",
+];
+
+fn main() {
+    tauri_plugin::Builder::new(COMMANDS).build();
+}
@@ -0,0 +1,692 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{borrow::Cow, collections::HashSet, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use sqlx::types::{BigDecimal, Uuid};
+use time::{
+    format_description::{well_known::Rfc3339, FormatItem},
+    macros::format_description,
+    Date, Duration, OffsetDateTime, PrimitiveDateTime, Time,
+};
+
+use crate::{
+    codec::TypeCodecs,
+    error::{Error, Result},
+    geometry::Geometry,
+    sql::{dollar_quote_tag, find, ident_len, utf8_len},
+};
+
+/// A bind parameter, typed from the JSON value sent by the frontend.
+///
+/// Plain JSON values map to the closest SQL type: numbers to integers or floats,
+/// booleans to booleans, strings to text and objects to JSON. Arrays are expanded
+/// by [`BindValues::into_positional`] beforehand. Other types are requested with
+/// the tagged form `{ "$type": "uuid", "value": "..." }`.
+#[derive(Debug)]
+pub(crate) enum BindValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    /// Bytes, not supported by MSSQL.
+    #[cfg_attr(
+        not(any(feature = "sqlite", feature = "mysql", feature = "postgres")),
+        allow(dead_code)
+    )]
+    Bytes(Vec<u8>),
+    Json(JsonValue),
+    Uuid(Uuid),
+    Date(Date),
+    Time(Time),
+    /// A date and time without time zone.
+    DateTime(PrimitiveDateTime),
+    Timestamp(OffsetDateTime),
+    Decimal(BigDecimal),
+    /// A geometry read from GeoJSON, not supported by MSSQL.
+    #[cfg_attr(
+        not(any(feature = "sqlite", feature = "mysql", feature = "postgres")),
+        allow(dead_code)
+    )]
+    Geometry(Geometry),
+    /// A native array, only supported by Postgres.
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    Array(Vec<BindValue>),
+}
+
+impl TryFrom<JsonValue> for BindValue {
+    type Error = Error;
+
+    fn try_from(value: JsonValue) -> Result<Self> {
+        Ok(match value {
+            JsonValue::Null => Self::Null,
+            JsonValue::Bool(b) => Self::Bool(b),
+            JsonValue::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    Self::Int(i)
+                } else if n.is_u64() {
+                    // too large for a signed integer, keep every digit
+                    Self::Decimal(decimal(&n.to_string())?)
+                } else {
+                    Self::Float(n.as_f64().unwrap_or_default())
+                }
+            }
+            JsonValue::String(s) => Self::Text(s),
+            JsonValue::Object(object) if is_tagged(&object) => tagged(object)?,
+            value => Self::Json(value),
+        })
+    }
+}
+
+/// Whether `object` is a `{ "$type", "value" }` pair rather than a JSON document.
+fn is_tagged(object: &Map<String, JsonValue>) -> bool {
+    object.len() == 2 && object.contains_key("$type") && object.contains_key("value")
+}
+
+fn tagged(mut object: Map<String, JsonValue>) -> Result<BindValue> {
+    let value = object.remove("value").unwrap_or_default();
+    let ty = match object.remove("$type") {
+        Some(JsonValue::String(ty)) => ty,
+        ty => return Err(invalid(format!("`$type` must be a string, got {ty:?}"))),
+    };
+
+    if value.is_null() {
+        return Ok(BindValue::Null);
+    }
+
+    Ok(match ty.as_str() {
+        "uuid" => BindValue::Uuid(
+            Uuid::parse_str(as_str(&ty, &value)?).map_err(|e| invalid(format!("uuid: {e}")))?,
+        ),
+        "date" => BindValue::Date(match &value {
+            JsonValue::Number(_) => epoch_millis(&ty, &value)?.date(),
+            _ => Date::parse(as_str(&ty, &value)?, DATE)
+                .map_err(|e| invalid(format!("date: {e}")))?,
+        }),
+        "time" => BindValue::Time(match &value {
+            JsonValue::Number(_) => match millis(&ty, &value)? {
+                ms @ 0..=86_399_999 => Time::MIDNIGHT + Duration::milliseconds(ms),
+                ms => return Err(invalid(format!("time must be within a day, got {ms} ms"))),
+            },
+            _ => Time::parse(as_str(&ty, &value)?, TIME)
+                .map_err(|e| invalid(format!("time: {e}")))?,
+        }),
+        "datetime" => BindValue::DateTime(match &value {
+            JsonValue::Number(_) => {
+                let instant = epoch_millis(&ty, &value)?;
+                PrimitiveDateTime::new(instant.date(), instant.time())
+            }
+            _ => PrimitiveDateTime::parse(as_str(&ty, &value)?, DATE_TIME)
+                .map_err(|e| invalid(format!("datetime: {e}")))?,
+        }),
+        "timestamp" => BindValue::Timestamp(match &value {
+            JsonValue::Number(_) => epoch_millis(&ty, &value)?,
+            _ => OffsetDateTime::parse(as_str(&ty, &value)?, &Rfc3339)
+                .map_err(|e| invalid(format!("timestamp: {e}")))?,
+        }),
+        "bytes" => BindValue::Bytes(
+            serde_json::from_value(value)
+                .map_err(|e| invalid(format!("bytes must be an array of octets: {e}")))?,
+        ),
+        "base64" => BindValue::Bytes(
+            STANDARD
+                .decode(as_str(&ty, &value)?)
+                .map_err(|e| invalid(format!("base64: {e}")))?,
+        ),
+        "hex" => BindValue::Bytes(
+            hex::decode(as_str(&ty, &value)?).map_err(|e| invalid(format!("hex: {e}")))?,
+        ),
+        "decimal" => match &value {
+            JsonValue::String(s) => BindValue::Decimal(decimal(s)?),
+            JsonValue::Number(n) => BindValue::Decimal(decimal(&n.to_string())?),
+            _ => return Err(invalid(format!("decimal must be a string, got {value}"))),
+        },
+        "json" => BindValue::Json(value),
+        "geojson" => BindValue::Geometry(
+            Geometry::from_geojson(&value).map_err(|e| invalid(format!("geojson: {e}")))?,
+        ),
+        "array" => match value {
+            JsonValue::Array(items) => BindValue::Array(
+                items
+                    .into_iter()
+                    .map(BindValue::try_from)
+                    .collect::<Result<_>>()?,
+            ),
+            _ => return Err(invalid(format!("array must be an array, got {value}"))),
+        },
+        _ => return Err(invalid(format!("unknown `$type` {ty}"))),
+    })
+}
+
+/// Encodes `value` with its codec if it is tagged with a registered type, looking into
+/// the arrays expanded to several parameters and the elements of native arrays.
+fn encode(value: JsonValue, codecs: &TypeCodecs) -> Result<JsonValue> {
+    Ok(match value {
+        JsonValue::Array(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .map(|item| encode(item, codecs))
+                .collect::<Result<_>>()?,
+        ),
+        JsonValue::Object(mut object) if is_tagged(&object) => {
+            let ty = object["$type"].as_str().unwrap_or_default().to_string();
+            let value = object.remove("value").unwrap_or_default();
+            match codecs.get(&ty) {
+                Some(codec) => codec
+                    .encode(value)
+                    .map_err(|e| invalid(format!("{ty}: {e}")))?,
+                None => {
+                    let value = match ty.as_str() {
+                        "array" => encode(value, codecs)?,
+                        _ => value,
+                    };
+                    object.insert("value".into(), value);
+                    JsonValue::Object(object)
+                }
+            }
+        }
+        value => value,
+    })
+}
+
+fn as_str<'a>(ty: &str, value: &'a JsonValue) -> Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| invalid(format!("{ty} must be a string, got {value}")))
+}
+
+/// The ISO 8601 forms of the `date`, `time` and `datetime` bind values.
+pub(crate) const DATE: &[FormatItem<'_>] = format_description!("[year]-[month]-[day]");
+pub(crate) const TIME: &[FormatItem<'_>] =
+    format_description!("[hour]:[minute]:[second][optional [.[subsecond]]]");
+pub(crate) const DATE_TIME: &[FormatItem<'_>] = format_description!(
+    "[year]-[month]-[day][first [T][t][ ]][hour]:[minute]:[second][optional [.[subsecond]]]"
+);
+
+/// Reads a whole number of milliseconds.
+fn millis(ty: &str, value: &JsonValue) -> Result<i64> {
+    value.as_i64().ok_or_else(|| {
+        invalid(format!(
+            "{ty} must be a whole number of milliseconds, got {value}"
+        ))
+    })
+}
+
+/// Reads a number of milliseconds since the Unix epoch, as UTC.
+fn epoch_millis(ty: &str, value: &JsonValue) -> Result<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis(ty, value)?) * 1_000_000)
+        .map_err(|e| invalid(format!("{ty}: {e}")))
+}
+
+fn decimal(s: &str) -> Result<BigDecimal> {
+    BigDecimal::from_str(s).map_err(|_| invalid(format!("{s:?} is not a decimal number")))
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidBindValue(message)
+}
+
+/// How a driver writes the placeholders of positional parameters.
+#[derive(Clone, Copy)]
+pub(crate) enum ParamStyle {
+    /// `?`, or `?N` to refer to the N-th parameter (SQLite, MySQL).
+    #[cfg(any(feature = "sqlite", feature = "mysql"))]
+    QuestionMark,
+    /// `$N` (Postgres).
+    #[cfg(feature = "postgres")]
+    Dollar,
+    /// `@pN` (MSSQL).
+    #[cfg(feature = "mssql")]
+    AtP,
+}
+
+impl ParamStyle {
+    /// Returns the placeholder of the parameter `index`, starting at 1.
+    fn placeholder(self, #[allow(unused_variables)] index: usize) -> String {
+        match self {
+            #[cfg(any(feature = "sqlite", feature = "mysql"))]
+            Self::QuestionMark => "?".into(),
+            #[cfg(feature = "postgres")]
+            Self::Dollar => format!("${index}"),
+            #[cfg(feature = "mssql")]
+            Self::AtP => format!("@p{index}"),
+        }
+    }
+}
+
+/// A parameter placeholder found in a query.
+enum Param<'a> {
+    /// `:name` or `@name`.
+    Named(&'a str),
+    /// `$N`, `?N` or `@pN`, starting at 1. A bare `?` is the parameter following the previous one.
+    Numbered(usize),
+}
+
+/// The parameters of a query, either in placeholder order or by name.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum BindValues {
+    Positional(Vec<JsonValue>),
+    Named(Map<String, JsonValue>),
+}
+
+impl Default for BindValues {
+    fn default() -> Self {
+        Self::Positional(Vec::new())
+    }
+}
+
+impl BindValues {
+    /// Replaces the values tagged with the type of a registered codec by their encoding.
+    pub(crate) fn encode(self, codecs: &TypeCodecs) -> Result<Self> {
+        if codecs.is_empty() {
+            return Ok(self);
+        }
+        Ok(match self {
+            Self::Positional(values) => Self::Positional(
+                values
+                    .into_iter()
+                    .map(|value| encode(value, codecs))
+                    .collect::<Result<_>>()?,
+            ),
+            Self::Named(named) => Self::Named(
+                named
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, encode(value, codecs)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+
+    /// Returns `query` and its values in the positional form of `style`.
+    ///
+    /// Named values replace the `:name` and `@name` placeholders of `query`, every
+    /// occurrence of a name is bound as its own parameter. With `at_variables`, an
+    /// `@name` without a value is a variable of the database and is left as written.
+    /// With `brackets`, `[...]` is a quoted identifier rather than an array subscript or
+    /// constructor, so the placeholders inside are ignored. Array values are expanded to one parameter per element so they can be used in
+    /// `IN (...)` clauses.
+    pub(crate) fn into_positional(
+        self,
+        query: &str,
+        style: ParamStyle,
+        at_variables: bool,
+        brackets: bool,
+    ) -> Result<(Cow<'_, str>, Vec<JsonValue>)> {
+        match self {
+            Self::Positional(values) if !values.iter().any(JsonValue::is_array) => {
+                Ok((Cow::Borrowed(query), values))
+            }
+            Self::Positional(values) => {
+                let (query, values) = rewrite(query, style, false, brackets, |param, written| {
+                    let Param::Numbered(n) = param else {
+                        unreachable!("named parameters aren't parsed")
+                    };
+                    values
+                        .get(n.wrapping_sub(1))
+                        .map(Some)
+                        .ok_or_else(|| Error::MissingParameter(written.to_string()))
+                })?;
+                Ok((Cow::Owned(query), values))
+            }
+            Self::Named(named) => {
+                let mut used = HashSet::new();
+                let (query, values) = rewrite(query, style, true, brackets, |param, written| {
+                    let Param::Named(name) = param else {
+                        unreachable!("positional parameters aren't parsed")
+                    };
+                    match named.get(name) {
+                        Some(value) => {
+                            used.insert(name.to_string());
+                            Ok(Some(value))
+                        }
+                        None if at_variables && written.starts_with('@') => Ok(None),
+                        None => Err(Error::MissingParameter(written.to_string())),
+                    }
+                })?;
+
+                let mut unused: Vec<&str> = named
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|name| !used.contains(*name))
+                    .collect();
+                if !unused.is_empty() {
+                    unused.sort_unstable();
+                    return Err(Error::UnusedParameters(unused.join(", ")));
+                }
+                Ok((Cow::Owned(query), values))
+            }
+        }
+    }
+}
+
+/// Rewrites the placeholders of `query` to consecutive parameters of `style`, calling
+/// `value_of` with each parameter and the placeholder as written in `query`. The
+/// placeholders it returns no value for are left as written.
+///
+/// Named placeholders are recognized when `named` is set, the positional ones of `style`
+/// otherwise. `brackets` makes `[...]` a quoted identifier. An array value becomes one
+/// parameter per element, or `NULL` when empty so that `IN (...)` matches no row.
+fn rewrite<'v>(
+    query: &str,
+    style: ParamStyle,
+    named: bool,
+    brackets: bool,
+    mut value_of: impl FnMut(Param<'_>, &str) -> Result<Option<&'v JsonValue>>,
+) -> Result<(String, Vec<JsonValue>)> {
+    let bytes = query.as_bytes();
+    let mut sql = String::with_capacity(query.len());
+    let mut values = Vec::new();
+    let mut last = 0;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        let end = match bytes[i] {
+            // literals, quoted identifiers and comments are copied untouched
+            quote @ (b'\'' | b'"' | b'`') => find(bytes, i + 1, &[quote]),
+            b'[' if brackets => find(bytes, i + 1, b"]"),
+            b'-' if next == Some(b'-') => find(bytes, i + 2, b"\n"),
+            b'/' if next == Some(b'*') => find(bytes, i + 2, b"*/"),
+            b'$' => match dollar_quote_tag(bytes, i) {
+                Some(tag) => find(bytes, i + tag.len(), tag),
+                None => i + 1,
+            },
+            // postgres casts and MSSQL/MySQL system variables
+            b':' if next == Some(b':') => i + 2,
+            b'@' if next == Some(b'@') => i + 2,
+            c => i + utf8_len(c),
+        };
+
+        let param = if named {
+            named_param(query, i)
+        } else {
+            positional_param(query, i, style, &mut last)
+        };
+        let Some((param, param_end)) = param else {
+            sql.push_str(&query[i..end]);
+            i = end;
+            continue;
+        };
+
+        match value_of(param, &query[i..param_end])? {
+            None => sql.push_str(&query[i..param_end]),
+            Some(JsonValue::Array(items)) if items.is_empty() => sql.push_str("NULL"),
+            Some(JsonValue::Array(items)) => {
+                for (n, item) in items.iter().enumerate() {
+                    if n > 0 {
+                        sql.push_str(", ");
+                    }
+                    values.push(item.clone());
+                    sql.push_str(&style.placeholder(values.len()));
+                }
+            }
+            Some(value) => {
+                values.push(value.clone());
+                sql.push_str(&style.placeholder(values.len()));
+            }
+        }
+        i = param_end;
+    }
+
+    Ok((sql, values))
+}
+
+/// Parses a `:name` or `@name` placeholder at `start`, returning it with its end.
+fn named_param(query: &str, start: usize) -> Option<(Param<'_>, usize)> {
+    let bytes = query.as_bytes();
+    let prefixed = matches!(bytes[start], b':' | b'@');
+    let previous = start.checked_sub(1).map(|i| bytes[i]);
+    let name_start = bytes
+        .get(start + 1)
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_');
+    // `::type` casts and `@@variables` aren't parameters
+    if !prefixed || !name_start || previous == Some(bytes[start]) {
+        return None;
+    }
+
+    let end = start + 1 + ident_len(&bytes[start + 1..]);
+    Some((Param::Named(&query[start + 1..end]), end))
+}
+
+/// Parses a positional placeholder of `style` at `start`, returning it with its end.
+///
+/// `last` is the number of the previous parameter, used to number a bare `?`.
+fn positional_param<'a>(
+    query: &'a str,
+    start: usize,
+    style: ParamStyle,
+    last: &mut usize,
+) -> Option<(Param<'a>, usize)> {
+    let bytes = query.as_bytes();
+    let digits_start = match (style, bytes[start], bytes.get(start + 1)) {
+        #[cfg(any(feature = "sqlite", feature = "mysql"))]
+        (ParamStyle::QuestionMark, b'?', _) => start + 1,
+        #[cfg(feature = "postgres")]
+        (ParamStyle::Dollar, b'$', _) => start + 1,
+        #[cfg(feature = "mssql")]
+        (ParamStyle::AtP, b'@', Some(b'p' | b'P')) => start + 2,
+        _ => return None,
+    };
+    let digits = bytes[digits_start..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    let end = digits_start + digits;
+    *last = match query[digits_start..end].parse() {
+        Ok(n) => n,
+        Err(_) if bytes[start] == b'?' => *last + 1,
+        Err(_) => return None,
+    };
+    Some((Param::Numbered(*last), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn positional(
+        query: &str,
+        values: JsonValue,
+        style: ParamStyle,
+        at_variables: bool,
+        brackets: bool,
+    ) -> Result<(String, Vec<JsonValue>)> {
+        let values: BindValues = serde_json::from_value(values).unwrap();
+        let (sql, values) = values.into_positional(query, style, at_variables, brackets)?;
+        Ok((sql.into_owned(), values))
+    }
+
+    #[cfg(feature = "postgres")]
+    fn postgres(query: &str, values: JsonValue) -> Result<(String, Vec<JsonValue>)> {
+        positional(query, values, ParamStyle::Dollar, false, false)
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn named_values_are_numbered_in_order() {
+        let (sql, values) = postgres(
+            "SELECT * FROM t WHERE a = :a AND b = @b OR a = :a",
+            json!({"a": 1, "b": "x"}),
+        )
+        .unwrap();
+        assert_eq!(sql, "SELECT * FROM t WHERE a = $1 AND b = $2 OR a = $3");
+        assert_eq!(values, [json!(1), json!("x"), json!(1)]);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn literals_and_comments_are_untouched() {
+        let query =
+            "SELECT ':a', \"@a\", $$ :a $$, $tag$ @a $tag$ -- :a\n/* @a */ FROM t WHERE a = :a";
+        let (sql, values) = postgres(query, json!({"a": 1})).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT ':a', \"@a\", $$ :a $$, $tag$ @a $tag$ -- :a\n/* @a */ FROM t WHERE a = $1"
+        );
+        assert_eq!(values, [json!(1)]);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn casts_and_system_variables_are_not_parameters() {
+        let (sql, _) = postgres("SELECT :a::text, @@version", json!({"a": 1})).unwrap();
+        assert_eq!(sql, "SELECT $1::text, @@version");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn brackets_are_array_syntax() {
+        let (sql, values) = postgres(
+            "SELECT ARRAY[:a, :b], tags[:i], ARRAY[$x$]$x$] FROM t WHERE id = :id",
+            json!({"a": 1, "b": 2, "i": 3, "id": 4}),
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT ARRAY[$1, $2], tags[$3], ARRAY[$x$]$x$] FROM t WHERE id = $4"
+        );
+        assert_eq!(values, [json!(1), json!(2), json!(3), json!(4)]);
+
+        let (sql, _) = postgres("SELECT ARRAY[$1], $2", json!([[1, 2], 3])).unwrap();
+        assert_eq!(sql, "SELECT ARRAY[$1, $2], $3");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn arrays_are_expanded() {
+        let (sql, values) = postgres(
+            "SELECT * FROM t WHERE id IN ($1) AND kind = $2",
+            json!([[1, 2, 3], "a"]),
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM t WHERE id IN ($1, $2, $3) AND kind = $4"
+        );
+        assert_eq!(values, [json!(1), json!(2), json!(3), json!("a")]);
+
+        let (sql, values) =
+            postgres("SELECT * FROM t WHERE id IN (:ids)", json!({"ids": []})).unwrap();
+        assert_eq!(sql, "SELECT * FROM t WHERE id IN (NULL)");
+        assert!(values.is_empty());
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn positional_values_without_arrays_are_kept() {
+        let (sql, values) = postgres("SELECT $1, '$2'", json!([1])).unwrap();
+        assert_eq!(sql, "SELECT $1, '$2'");
+        assert_eq!(values, [json!(1)]);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn missing_and_unused_names_are_reported() {
+        let error = postgres("SELECT :a, :b", json!({"a": 1})).err().unwrap();
+        assert!(matches!(error, Error::MissingParameter(name) if name == ":b"));
+
+        let error = postgres("SELECT :a", json!({"a": 1, "c": 2, "b": 3}))
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::UnusedParameters(names) if names == "b, c"));
+
+        let error = postgres("SELECT $1, $3", json!([[1], 2])).err().unwrap();
+        assert!(matches!(error, Error::MissingParameter(name) if name == "$3"));
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn question_marks_are_numbered_after_the_previous_one() {
+        let (sql, values) = positional(
+            "SELECT ?, ?3, ?",
+            json!([[1, 2], "b", "c", "d"]),
+            ParamStyle::QuestionMark,
+            true,
+            false,
+        )
+        .unwrap();
+        assert_eq!(sql, "SELECT ?, ?, ?, ?");
+        assert_eq!(values, [json!(1), json!(2), json!("c"), json!("d")]);
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn variables_without_a_value_are_kept() {
+        let mysql =
+            |query, values| positional(query, values, ParamStyle::QuestionMark, true, false);
+
+        let (sql, values) = mysql(
+            "SELECT @rank := @rank + 1 AS rank, name FROM users WHERE team = :team",
+            json!({"team": "a"}),
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT @rank := @rank + 1 AS rank, name FROM users WHERE team = ?"
+        );
+        assert_eq!(values, [json!("a")]);
+
+        // a name with a value is a parameter even when written `@name`
+        let (sql, values) = mysql(
+            "UPDATE users SET score = @score WHERE id = :id",
+            json!({"score": 5, "id": 1}),
+        )
+        .unwrap();
+        assert_eq!(sql, "UPDATE users SET score = ? WHERE id = ?");
+        assert_eq!(values, [json!(5), json!(1)]);
+
+        let error = mysql("SELECT :missing", json!({})).err().unwrap();
+        assert!(matches!(error, Error::MissingParameter(name) if name == ":missing"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_reports_missing_at_names() {
+        let error = positional(
+            "SELECT @a",
+            json!({}),
+            ParamStyle::QuestionMark,
+            false,
+            true,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, Error::MissingParameter(name) if name == "@a"));
+    }
+
+    #[cfg(feature = "mssql")]
+    #[test]
+    fn mssql_placeholders_and_locals() {
+        let (sql, values) = positional(
+            "DECLARE @n INT = @p1; SELECT [@id], @n, @id FROM t WHERE id IN (@p2)",
+            json!([1, [2, 3]]),
+            ParamStyle::AtP,
+            true,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "DECLARE @n INT = @p1; SELECT [@id], @n, @id FROM t WHERE id IN (@p2, @p3)"
+        );
+        assert_eq!(values, [json!(1), json!(2), json!(3)]);
+
+        let (sql, _) = positional(
+            "DECLARE @n INT = @id; SELECT @n",
+            json!({"id": 1}),
+            ParamStyle::AtP,
+            true,
+            true,
+        )
+        .unwrap();
+        assert_eq!(sql, "DECLARE @n INT = @p1; SELECT @n");
+    }
+}
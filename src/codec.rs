@@ -0,0 +1,74 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde_json::Value as JsonValue;
+
+use std::{error::Error as StdError, sync::Arc};
+
+/// The error returned by a [`TypeCodec`].
+pub type CodecError = Box<dyn StdError + Send + Sync>;
+
+/// A value handed to [`TypeCodec::decode`], as sent by the database.
+#[derive(Debug, Clone, Copy)]
+pub enum RawValue<'r> {
+    /// A value in the binary format of its type: Postgres values in the binary format,
+    /// all MySQL values, which sqlx reads with the binary protocol, SQLite blobs and the
+    /// little-endian bytes of MSSQL integers, floats and `BIT` values.
+    Binary(&'r [u8]),
+    /// A value in the text format of its type: Postgres values in the text format,
+    /// SQLite values of the other storage classes, and MSSQL strings and the values
+    /// the server converts to strings.
+    Text(&'r str),
+}
+
+/// Decodes and encodes the values of a SQL type the plugin doesn't know, such as a
+/// PostGIS geometry or a domain type.
+///
+/// Codecs are registered for a type name with [`Builder::add_codec`](crate::Builder::add_codec)
+/// and take precedence over the built-in decoders. The name is compared case-insensitively
+/// with the one reported by the driver, e.g. `geometry` for Postgres or `GEOMETRY` for
+/// MySQL. SQLite only reports the affinity of declared types, such as `TEXT` or `BLOB`.
+///
+/// MSSQL codecs are limited to the types sqlx reads: `CHAR`, `VARCHAR`, `NCHAR` and
+/// `NVARCHAR` values are passed as [`RawValue::Text`], integers, floats and `BIT` as
+/// [`RawValue::Binary`]. `DECIMAL`, `MONEY`, date and time, `UNIQUEIDENTIFIER` and binary
+/// values are passed as the [`RawValue::Text`] the server converts them to, binary
+/// values in hexadecimal. Values of the other types never reach a codec.
+pub trait TypeCodec: Send + Sync + 'static {
+    /// Converts a value of the type to JSON.
+    ///
+    /// An error gives a `null` value, or fails the query in strict mode.
+    fn decode(&self, value: RawValue<'_>) -> Result<JsonValue, CodecError>;
+
+    /// Converts the `value` of a bind value tagged with the type, such as
+    /// `{ "$type": "geometry", "value": ... }`, to a plain or tagged bind value of a
+    /// built-in type.
+    ///
+    /// Returns `value` unchanged by default.
+    fn encode(&self, value: JsonValue) -> Result<JsonValue, CodecError> {
+        Ok(value)
+    }
+}
+
+/// The codecs registered with the [`Builder`](crate::Builder), by type name.
+#[derive(Clone, Default)]
+pub(crate) struct TypeCodecs(Arc<Vec<(String, Box<dyn TypeCodec>)>>);
+
+impl TypeCodecs {
+    pub(crate) fn new(codecs: Vec<(String, Box<dyn TypeCodec>)>) -> Self {
+        Self(Arc::new(codecs))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the codec registered for `type_name`, ignoring case.
+    pub(crate) fn get(&self, type_name: &str) -> Option<&dyn TypeCodec> {
+        self.0
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(type_name))
+            .map(|(_, codec)| codec.as_ref())
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde::Serialize;
+use tauri::{async_runtime::JoinHandle, AppHandle, Manager, Runtime};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::Instant,
+};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    bind::BindValues,
+    error::{Error, Result},
+    row::{DecodeOptions, JsonRow},
+    wrapper::DbPool,
+};
+
+/// Number of rows read ahead of the last `fetch_cursor` call.
+const READ_AHEAD: usize = 100;
+
+/// A page of rows returned by `fetch_cursor`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CursorPage {
+    /// Index of the first returned row in the result set.
+    offset: usize,
+    rows: Vec<JsonRow>,
+    /// Whether the result set has no rows past this page.
+    done: bool,
+}
+
+/// The row stream of a query kept open on a dedicated connection.
+///
+/// Only the rows of the last fetched page and the ones read ahead of it are kept. A page
+/// before them is fetched by running the query again and skipping the rows up to its
+/// offset, it reflects the database at that time. Rows skipped by a jump forward are
+/// read and dropped.
+pub(crate) struct Cursor {
+    pool: DbPool,
+    query: String,
+    values: BindValues,
+    options: DecodeOptions,
+    /// The rows kept, starting at the row index `first`.
+    rows: VecDeque<JsonRow>,
+    first: usize,
+    receiver: mpsc::Receiver<Vec<JsonRow>>,
+    producer: Option<JoinHandle<Result<u64>>>,
+    /// Index of the row following the last fetched page.
+    position: usize,
+    last_used: Instant,
+}
+
+impl Cursor {
+    /// Starts reading the rows of `query` from `pool`.
+    ///
+    /// The connection is held until the cursor is dropped or all rows were read.
+    pub(crate) fn open(
+        pool: DbPool,
+        query: String,
+        values: BindValues,
+        options: DecodeOptions,
+    ) -> Self {
+        let (receiver, producer) = Self::read(&pool, &query, &values, &options);
+        Self {
+            pool,
+            query,
+            values,
+            options,
+            rows: VecDeque::new(),
+            first: 0,
+            receiver,
+            producer: Some(producer),
+            position: 0,
+            last_used: Instant::now(),
+        }
+    }
+
+    /// Runs the query, sending its rows `READ_AHEAD` at a time.
+    fn read(
+        pool: &DbPool,
+        query: &str,
+        values: &BindValues,
+        options: &DecodeOptions,
+    ) -> (mpsc::Receiver<Vec<JsonRow>>, JoinHandle<Result<u64>>) {
+        let (sender, receiver) = mpsc::channel(1);
+        let (pool, query, values, options) = (
+            pool.clone(),
+            query.to_string(),
+            values.clone(),
+            options.clone(),
+        );
+        let producer = tauri::async_runtime::spawn(async move {
+            pool.select_chunked(&query, values, &options, READ_AHEAD, sender)
+                .await
+        });
+        (receiver, producer)
+    }
+
+    /// Returns up to `n` rows starting at `offset`, or at the end of the previous page.
+    ///
+    /// Fails with the error of the query if it failed.
+    pub(crate) async fn fetch(&mut self, n: usize, offset: Option<usize>) -> Result<CursorPage> {
+        self.last_used = Instant::now();
+
+        let start = offset.unwrap_or(self.position);
+        if start < self.first {
+            // the rows were dropped, the previous producer stops once its receiver is gone
+            let (receiver, producer) =
+                Self::read(&self.pool, &self.query, &self.values, &self.options);
+            self.receiver = receiver;
+            self.producer = Some(producer);
+            self.rows.clear();
+            self.first = 0;
+        }
+
+        // one row past the page tells whether it is the last one
+        while self.first + self.rows.len() < start + n + 1 {
+            match self.receiver.recv().await {
+                Some(rows) => {
+                    self.rows.extend(rows);
+                    self.drop_before(start);
+                }
+                None => {
+                    // surface the error the query ended with, if any
+                    if let Some(producer) = self.producer.take() {
+                        producer.await??;
+                    }
+                    break;
+                }
+            }
+        }
+        self.drop_before(start);
+
+        let end = self.first + self.rows.len();
+        let page_end = (self.first + n).min(end);
+        self.position = page_end;
+        Ok(CursorPage {
+            offset: self.first,
+            rows: self.rows.range(..page_end - self.first).cloned().collect(),
+            done: page_end == end && self.producer.is_none(),
+        })
+    }
+
+    /// Drops the rows kept before the row index `start`.
+    fn drop_before(&mut self, start: usize) {
+        let dropped = start.saturating_sub(self.first).min(self.rows.len());
+        self.rows.drain(..dropped);
+        self.first += dropped;
+    }
+}
+
+/// An open cursor, keyed by its id in [`DbCursors`].
+struct CursorHandle {
+    db: String,
+    cursor: Arc<Mutex<Cursor>>,
+}
+
+#[derive(Default)]
+pub(crate) struct DbCursors(Mutex<HashMap<String, CursorHandle>>);
+
+impl DbCursors {
+    /// Registers `cursor` reading from `db` and closes it once it wasn't used for `idle_timeout`.
+    pub(crate) async fn insert<R: Runtime>(
+        &self,
+        app: AppHandle<R>,
+        db: String,
+        cursor: Cursor,
+        idle_timeout: Duration,
+    ) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.0.lock().await.insert(
+            id.clone(),
+            CursorHandle {
+                db,
+                cursor: Arc::new(Mutex::new(cursor)),
+            },
+        );
+
+        let cursor_id = id.clone();
+        tauri::async_runtime::spawn(async move {
+            let cursors = app.state::<DbCursors>();
+            let mut deadline = Instant::now() + idle_timeout;
+            loop {
+                tokio::time::sleep_until(deadline).await;
+
+                let Ok(cursor) = cursors.get(cursor_id.clone()).await else {
+                    return;
+                };
+                deadline = cursor.lock().await.last_used + idle_timeout;
+                if deadline <= Instant::now() {
+                    cursors.0.lock().await.remove(&cursor_id);
+                    return;
+                }
+            }
+        });
+
+        id
+    }
+
+    pub(crate) async fn get(&self, id: String) -> Result<Arc<Mutex<Cursor>>> {
+        match self.0.lock().await.get(&id) {
+            Some(handle) => Ok(handle.cursor.clone()),
+            None => Err(Error::CursorNotFound(id)),
+        }
+    }
+
+    /// Closes the cursor `id`, releasing its connection.
+    pub(crate) async fn remove(&self, id: String) -> Result<()> {
+        self.0
+            .lock()
+            .await
+            .remove(&id)
+            .map(drop)
+            .ok_or(Error::CursorNotFound(id))
+    }
+
+    /// Closes every cursor.
+    pub(crate) async fn clear(&self) {
+        self.0.lock().await.clear();
+    }
+
+    /// Closes every cursor reading from one of `dbs`.
+    pub(crate) async fn remove_for(&self, dbs: &[String]) {
+        self.0
+            .lock()
+            .await
+            .retain(|_, handle| !dbs.contains(&handle.db));
+    }
+}
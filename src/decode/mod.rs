@@ -2,23 +2,324 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+#[cfg(feature = "mssql")]
+pub(crate) mod mssql;
 #[cfg(feature = "mysql")]
-mod mysql;
+pub(crate) mod mysql;
 #[cfg(feature = "postgres")]
-mod postgres;
+pub(crate) mod postgres;
 #[cfg(feature = "sqlite")]
-mod sqlite;
-#[cfg(feature = "mssql")]
-mod mssql;
+pub(crate) mod sqlite;
+
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+use base64::{engine::general_purpose::STANDARD, Engine};
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+use serde_json::json;
+use serde_json::Value as JsonValue;
+#[cfg(any(feature = "mysql", feature = "postgres", feature = "mssql"))]
+use sqlx::types::BigDecimal;
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+use std::fmt::Display;
+
+use crate::{
+    codec::{CodecError, RawValue, TypeCodec},
+    row::{BigNumbers, DecodeOptions},
+    Error,
+};
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+use crate::row::{BlobEncoding, DateTimeFormat};
+
+/// The largest integer a JavaScript number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Returns `null` in place of a value that failed to decode, or the error in strict mode.
+///
+/// The column and row of the error are filled in by the caller of the decoder.
+pub(crate) fn failed(error: impl Display, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    if !options.strict {
+        return Ok(JsonValue::Null);
+    }
+    Err(Error::Decode {
+        column: String::new(),
+        type_name: String::new(),
+        row: 0,
+        message: error.to_string(),
+    })
+}
 
+/// Decodes a value with the `codec` registered for its type.
+pub(crate) fn custom(
+    codec: &dyn TypeCodec,
+    value: Result<RawValue<'_>, CodecError>,
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
+    match value.and_then(|value| codec.decode(value)) {
+        Ok(value) => Ok(value),
+        Err(e) => failed(e, options),
+    }
+}
+
+/// Returns the value of a binary column in the requested `encoding`.
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+pub(crate) fn bytes(bytes: Vec<u8>, encoding: BlobEncoding) -> JsonValue {
+    match encoding {
+        BlobEncoding::Raw => {
+            JsonValue::Array(bytes.into_iter().map(|n| JsonValue::Number(n.into())).collect())
+        }
+        BlobEncoding::Base64 => JsonValue::String(STANDARD.encode(bytes)),
+        BlobEncoding::Hex => JsonValue::String(hex::encode(bytes)),
+    }
+}
+
+/// Returns an integer column, as a string when it is outside the safe range of JavaScript numbers.
+pub(crate) fn int(n: i64, big_numbers: BigNumbers) -> JsonValue {
+    match big_numbers {
+        BigNumbers::String if n.unsigned_abs() > MAX_SAFE_INTEGER => {
+            JsonValue::String(n.to_string())
+        }
+        _ => JsonValue::Number(n.into()),
+    }
+}
+
+/// Returns an unsigned integer column, as a string when it is above `Number.MAX_SAFE_INTEGER`.
 #[cfg(feature = "mysql")]
-pub(crate) use mysql::to_json;
+pub(crate) fn uint(n: u64, big_numbers: BigNumbers) -> JsonValue {
+    match big_numbers {
+        BigNumbers::String if n > MAX_SAFE_INTEGER => JsonValue::String(n.to_string()),
+        _ => JsonValue::Number(n.into()),
+    }
+}
 
-#[cfg(feature = "postgres")]
-pub(crate) use postgres::to_json;
+/// Returns a decimal column, keeping its scale when returned as a string.
+#[cfg(any(feature = "mysql", feature = "postgres", feature = "mssql"))]
+pub(crate) fn decimal(n: BigDecimal, big_numbers: BigNumbers) -> JsonValue {
+    match big_numbers {
+        BigNumbers::String => JsonValue::String(n.to_string()),
+        BigNumbers::Number => n
+            .to_string()
+            .parse::<f64>()
+            .map(JsonValue::from)
+            .unwrap_or_default(),
+    }
+}
 
-#[cfg(feature = "sqlite")]
-pub(crate) use sqlite::to_json;
+/// The value of a date or time column.
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+pub(crate) enum Temporal {
+    Date(Date),
+    Time(Time),
+    /// A date and time without time zone.
+    DateTime(PrimitiveDateTime),
+    /// A date and time with a time zone, or an instant.
+    Timestamp(OffsetDateTime),
+}
 
-#[cfg(feature = "mssql")]
-pub(crate) use mssql::to_json;
+/// Returns a date or time column in the requested `format`.
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+pub(crate) fn temporal(value: Temporal, format: DateTimeFormat) -> JsonValue {
+    match format {
+        DateTimeFormat::Rfc3339 => JsonValue::String(value.to_rfc3339()),
+        DateTimeFormat::EpochMillis => JsonValue::Number(value.epoch_millis().into()),
+        DateTimeFormat::Tagged => json!({ "$type": value.tag(), "value": value.to_rfc3339() }),
+    }
+}
+
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+impl Temporal {
+    /// The `$type` binding a value back to the same SQL type.
+    fn tag(&self) -> &'static str {
+        match self {
+            Self::Date(_) => "date",
+            Self::Time(_) => "time",
+            Self::DateTime(_) => "datetime",
+            Self::Timestamp(_) => "timestamp",
+        }
+    }
+
+    fn to_rfc3339(&self) -> String {
+        match self {
+            Self::Date(d) => date(*d),
+            Self::Time(t) => time(*t),
+            Self::DateTime(dt) => format!("{}T{}", date(dt.date()), time(dt.time())),
+            Self::Timestamp(dt) => format!(
+                "{}T{}{}",
+                date(dt.date()),
+                time(dt.time()),
+                offset(dt.offset())
+            ),
+        }
+    }
+
+    fn epoch_millis(&self) -> i64 {
+        let instant = match self {
+            Self::Date(d) => d.midnight().assume_utc(),
+            Self::Time(t) => return (*t - Time::MIDNIGHT).whole_milliseconds() as i64,
+            Self::DateTime(dt) => dt.assume_utc(),
+            Self::Timestamp(dt) => *dt,
+        };
+        instant.unix_timestamp_nanos().div_euclid(1_000_000) as i64
+    }
+}
+
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+fn date(d: Date) -> String {
+    format!("{:04}-{:02}-{:02}", d.year(), d.month() as u8, d.day())
+}
+
+/// Formats `t` with as many fractional digits as needed, none for whole seconds.
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+fn time(t: Time) -> String {
+    let hms = format!("{:02}:{:02}:{:02}", t.hour(), t.minute(), t.second());
+    match t.nanosecond() {
+        0 => hms,
+        nanos => format!("{hms}.{}", format!("{nanos:09}").trim_end_matches('0')),
+    }
+}
+
+#[cfg(any(
+    feature = "sqlite",
+    feature = "mysql",
+    feature = "postgres",
+    feature = "mssql"
+))]
+fn offset(offset: UtcOffset) -> String {
+    if offset.is_utc() {
+        return "Z".into();
+    }
+    let (hours, minutes, _) = offset.as_hms();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    format!("{sign}{:02}:{:02}", hours.unsigned_abs(), minutes.unsigned_abs())
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "sqlite",
+        feature = "mysql",
+        feature = "postgres",
+        feature = "mssql"
+    )
+))]
+mod tests {
+    use time::macros::{date, datetime, time};
+
+    use super::*;
+
+    fn formats(value: fn() -> Temporal) -> [JsonValue; 3] {
+        [
+            temporal(value(), DateTimeFormat::Rfc3339),
+            temporal(value(), DateTimeFormat::EpochMillis),
+            temporal(value(), DateTimeFormat::Tagged),
+        ]
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(
+            formats(|| Temporal::Date(date!(2024 - 01 - 31))),
+            [
+                json!("2024-01-31"),
+                json!(1_706_659_200_000_i64),
+                json!({"$type": "date", "value": "2024-01-31"}),
+            ]
+        );
+        assert_eq!(
+            formats(|| Temporal::Time(time!(13:45:00.5))),
+            [
+                json!("13:45:00.5"),
+                json!(49_500_500),
+                json!({"$type": "time", "value": "13:45:00.5"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn date_times_without_time_zone_are_taken_as_utc() {
+        assert_eq!(
+            formats(|| Temporal::DateTime(datetime!(2024-01-31 13:45:00))),
+            [
+                json!("2024-01-31T13:45:00"),
+                json!(1_706_708_700_000_i64),
+                json!({"$type": "datetime", "value": "2024-01-31T13:45:00"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_keep_their_offset() {
+        assert_eq!(
+            formats(|| Temporal::Timestamp(datetime!(2024-01-31 13:45:00.123 +02:30))),
+            [
+                json!("2024-01-31T13:45:00.123+02:30"),
+                json!(1_706_699_700_123_i64),
+                json!({"$type": "timestamp", "value": "2024-01-31T13:45:00.123+02:30"}),
+            ]
+        );
+        assert_eq!(
+            temporal(
+                Temporal::Timestamp(datetime!(1969-12-31 23:59:59.999 UTC)),
+                DateTimeFormat::Rfc3339
+            ),
+            json!("1969-12-31T23:59:59.999Z")
+        );
+        assert_eq!(
+            temporal(
+                Temporal::Timestamp(datetime!(1969-12-31 23:59:59.9995 UTC)),
+                DateTimeFormat::EpochMillis
+            ),
+            json!(-1)
+        );
+    }
+}
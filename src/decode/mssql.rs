@@ -1,44 +1,787 @@
-vuse serde_json::Value as JsonValue;
-use sqlx::{value::ValueRef, TypeInfo};
-use std::str;
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
 
-use crate::Error;
+use serde_json::Value as JsonValue;
+use sqlx::{
+    error::BoxDynError,
+    mssql::{Mssql, MssqlConnection, MssqlTypeInfo, MssqlValueRef},
+    types::{BigDecimal, Uuid},
+    Decode, Row, Type, TypeInfo, ValueRef,
+};
+use time::{
+    error::Parse, format_description::FormatItem, macros::format_description, Date,
+    PrimitiveDateTime, Time, UtcOffset,
+};
 
-pub fn to_json(value: sqlx::value::RawValue<'_>) -> Result<JsonValue, Error> {
-    let type_info = value.type_info();
+use std::collections::HashSet;
 
-    match type_info.name() {
-        // Common integer types
-        "INT" | "BIGINT" | "SMALLINT" | "TINYINT" | "INTEGER" => {
-            Ok(JsonValue::Number(value.as_i64()?.into()))
+use crate::{
+    bind::{BindValue, DATE, DATE_TIME, TIME},
+    codec::RawValue,
+    decode::Temporal,
+    row::DecodeOptions,
+    sql::{spanned_tokens, Token},
+    Error,
+};
+
+/// The offset of a `DATETIMEOFFSET` converted to a string, such as `+01:00`.
+const OFFSET: &[FormatItem<'_>] =
+    format_description!("[offset_hour sign:mandatory]:[offset_minute]");
+
+/// The type of an MSSQL column, as far as sqlx can read it.
+///
+/// sqlx 0.6 only decodes integers, floats, booleans and strings from MSSQL. The bytes
+/// of `DECIMAL`, `MONEY`, date and time, `UNIQUEIDENTIFIER` and binary values are kept
+/// private, so the server converts those columns to strings, see [`conversion`]. The
+/// types from `DATE` on are the ones of converted columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MssqlType {
+    Null,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Float,
+    Bit,
+    Char,
+    VarChar,
+    NChar,
+    NVarChar,
+    Date,
+    Time,
+    DateTime,
+    SmallDateTime,
+    DateTime2,
+    DateTimeOffset,
+    Decimal,
+    Numeric,
+    Money,
+    SmallMoney,
+    UniqueIdentifier,
+    Binary,
+    VarBinary,
+    /// Any type sqlx can't decode, in a column that isn't converted.
+    Unsupported,
+}
+
+impl MssqlType {
+    /// Classifies `type_info` with the `compatible` checks of the Rust types sqlx decodes,
+    /// `MssqlTypeInfo::name` panics for the other types and for `BIT`.
+    pub(crate) fn of(type_info: &MssqlTypeInfo) -> Self {
+        fn is<T: Type<Mssql>>(type_info: &MssqlTypeInfo) -> bool {
+            T::compatible(type_info)
+        }
+
+        if type_info.is_null() {
+            Self::Null
+        } else if is::<u8>(type_info) {
+            Self::TinyInt
+        } else if is::<i16>(type_info) {
+            Self::SmallInt
+        } else if is::<i32>(type_info) {
+            Self::Int
+        } else if is::<i64>(type_info) {
+            Self::BigInt
+        } else if is::<f32>(type_info) {
+            Self::Real
+        } else if is::<f64>(type_info) {
+            Self::Float
+        } else if is::<bool>(type_info) {
+            Self::Bit
+        } else if is::<String>(type_info) {
+            match type_info.name() {
+                "NCHAR" => Self::NChar,
+                "NVARCHAR" => Self::NVarChar,
+                "CHAR" | "BIGCHAR" => Self::Char,
+                _ => Self::VarChar,
+            }
+        } else {
+            Self::Unsupported
         }
+    }
 
-        // MSSQL and Postgres float types
-        "FLOAT" | "REAL" | "DOUBLE PRECISION" | "DECIMAL" | "NUMERIC" | "MONEY" | "SMALLMONEY" => {
-            Ok(JsonValue::Number(
-                serde_json::Number::from_f64(value.as_f64()?).ok_or_else(|| {
-                    Error::UnsupportedDatatype("Invalid float conversion".to_string())
-                })?,
-            ))
+    /// Returns the SQL name of the type.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Null => "NULL",
+            Self::TinyInt => "TINYINT",
+            Self::SmallInt => "SMALLINT",
+            Self::Int => "INT",
+            Self::BigInt => "BIGINT",
+            Self::Real => "REAL",
+            Self::Float => "FLOAT",
+            Self::Bit => "BIT",
+            Self::Char => "CHAR",
+            Self::VarChar => "VARCHAR",
+            Self::NChar => "NCHAR",
+            Self::NVarChar => "NVARCHAR",
+            Self::Date => "DATE",
+            Self::Time => "TIME",
+            Self::DateTime => "DATETIME",
+            Self::SmallDateTime => "SMALLDATETIME",
+            Self::DateTime2 => "DATETIME2",
+            Self::DateTimeOffset => "DATETIMEOFFSET",
+            Self::Decimal => "DECIMAL",
+            Self::Numeric => "NUMERIC",
+            Self::Money => "MONEY",
+            Self::SmallMoney => "SMALLMONEY",
+            Self::UniqueIdentifier => "UNIQUEIDENTIFIER",
+            Self::Binary => "BINARY",
+            Self::VarBinary => "VARBINARY",
+            Self::Unsupported => "UNKNOWN",
         }
+    }
 
-        // Boolean
-        "BIT" | "BOOLEAN" => Ok(JsonValue::Bool(value.as_bool()?)),
+    /// Returns the type of a column the server converts to a string, from the
+    /// `system_type_name` describing it such as `decimal(10,2)`.
+    ///
+    /// Binary values longer than 2000 bytes don't fit the `NVARCHAR(4000)` their hex
+    /// digits are read as, sqlx doesn't read `MAX` types.
+    fn converted(system_type_name: &str) -> Option<Self> {
+        let (name, size) = match system_type_name.split_once('(') {
+            Some((name, size)) => (name, size.trim_end_matches(')').parse::<u16>().ok()),
+            None => (system_type_name, None),
+        };
+        Some(match name.to_ascii_lowercase().as_str() {
+            "date" => Self::Date,
+            "time" => Self::Time,
+            "datetime" => Self::DateTime,
+            "smalldatetime" => Self::SmallDateTime,
+            "datetime2" => Self::DateTime2,
+            "datetimeoffset" => Self::DateTimeOffset,
+            "decimal" => Self::Decimal,
+            "numeric" => Self::Numeric,
+            "money" => Self::Money,
+            "smallmoney" => Self::SmallMoney,
+            "uniqueidentifier" => Self::UniqueIdentifier,
+            "binary" if size.is_some_and(|n| n <= 2000) => Self::Binary,
+            "varbinary" if size.is_some_and(|n| n <= 2000) => Self::VarBinary,
+            _ => return None,
+        })
+    }
 
-        // Strings and text
-        "CHAR" | "NCHAR" | "VARCHAR" | "NVARCHAR" | "TEXT" | "NTEXT" | "STRING" => {
-            Ok(JsonValue::String(value.as_str()?.to_string()))
+    /// Returns the expression converting `column` of this type to a string.
+    fn conversion(self, column: &str) -> String {
+        match self {
+            // the default style of the other date and time types is ISO 8601 already
+            Self::DateTime | Self::SmallDateTime => format!("CONVERT(NVARCHAR(40), {column}, 121)"),
+            Self::Money | Self::SmallMoney => format!("CONVERT(NVARCHAR(40), {column}, 2)"),
+            Self::Binary | Self::VarBinary => {
+                format!("CONVERT(NVARCHAR(4000), CONVERT(VARCHAR(4000), {column}, 2))")
+            }
+            _ => format!("CONVERT(NVARCHAR(40), {column})"),
         }
+    }
+
+    /// Whether columns of this type are converted to strings by the server.
+    fn is_converted(self) -> bool {
+        matches!(
+            self,
+            Self::Date
+                | Self::Time
+                | Self::DateTime
+                | Self::SmallDateTime
+                | Self::DateTime2
+                | Self::DateTimeOffset
+                | Self::Decimal
+                | Self::Numeric
+                | Self::Money
+                | Self::SmallMoney
+                | Self::UniqueIdentifier
+                | Self::Binary
+                | Self::VarBinary
+        )
+    }
+}
+
+/// How an MSSQL query is run for the server to convert the columns sqlx can't read.
+#[derive(Clone, Debug)]
+pub(crate) struct Conversion {
+    /// The query wrapping the original one.
+    pub(crate) query: String,
+    /// The type of each converted column, by ordinal.
+    pub(crate) columns: Vec<Option<MssqlType>>,
+}
+
+/// Describes the result set of `sql`, with the positional parameters `values`, and
+/// returns how to run it so that the columns sqlx can't read come back as strings.
+///
+/// Returns `None` when no column needs converting, or when `sql` can't be described or
+/// wrapped, see [`readable_query`]. Its values are then reported as unsupported.
+pub(crate) async fn conversion(
+    conn: &mut MssqlConnection,
+    sql: &str,
+    values: &[JsonValue],
+) -> Result<Option<Conversion>, Error> {
+    let mut parameters = Vec::with_capacity(values.len());
+    for (i, value) in values.iter().enumerate() {
+        // the errors of the values are reported when the query is run
+        let Ok(value) = BindValue::try_from(value.clone()) else {
+            return Ok(None);
+        };
+        let sql_type = match value {
+            BindValue::Bool(_) => "bit",
+            BindValue::Int(_) => "bigint",
+            BindValue::Float(_) => "float",
+            _ => "nvarchar(4000)",
+        };
+        parameters.push(format!("@p{} {sql_type}", i + 1));
+    }
+
+    // a query that can't be described is run as is, failing with its own error
+    let Ok(rows) = sqlx::query(
+        "SELECT name, system_type_name \
+         FROM sys.dm_exec_describe_first_result_set(@p1, @p2, 0) ORDER BY column_ordinal",
+    )
+    .bind(sql)
+    .bind((!parameters.is_empty()).then(|| parameters.join(", ")))
+    .fetch_all(conn)
+    .await
+    else {
+        return Ok(None);
+    };
 
-        // Date and time types
-        "DATE" | "DATETIME" | "DATETIME2" | "SMALLDATETIME" | "TIMESTAMP" | "TIME" => {
-            Ok(JsonValue::String(value.as_str()?.to_string()))
+    // the server reports why it can't describe a query in a row without name
+    let mut columns = Vec::with_capacity(rows.len());
+    for row in rows {
+        let (Ok(Some(name)), Ok(Some(system_type_name))) = (
+            row.try_get::<Option<String>, _>(0),
+            row.try_get::<Option<String>, _>(1),
+        ) else {
+            return Ok(None);
+        };
+        columns.push((name, MssqlType::converted(&system_type_name)));
+    }
+    if columns.iter().all(|(_, column_type)| column_type.is_none()) {
+        return Ok(None);
+    }
+
+    Ok(readable_query(sql, &columns).map(|query| Conversion {
+        query,
+        columns: columns
+            .into_iter()
+            .map(|(_, column_type)| column_type)
+            .collect(),
+    }))
+}
+
+/// Wraps `sql` in a query selecting its `columns` by name, converting those with a type.
+///
+/// The query must be a single `SELECT`, possibly with common table expressions, whose
+/// columns all have a different name. `INTO`, `FOR` and `OPTION` clauses aren't
+/// supported. Its `ORDER BY` is moved to the outer query, kept inside along with `TOP`
+/// or `OFFSET`, and may only list output columns by name or position.
+fn readable_query(sql: &str, columns: &[(String, Option<MssqlType>)]) -> Option<String> {
+    let mut names = HashSet::new();
+    if !columns
+        .iter()
+        .all(|(name, _)| !name.is_empty() && names.insert(name.to_lowercase()))
+    {
+        return None;
+    }
+
+    // the tokens outside of parentheses, with the ones enclosing a nested expression
+    let mut depth = 0_usize;
+    let mut tokens = Vec::new();
+    for (span, token) in spanned_tokens(sql, true) {
+        match token {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+        let enclosing = matches!(token, Token::Punct('(')) && depth == 1
+            || matches!(token, Token::Punct(')')) && depth == 0;
+        if depth == 0 || enclosing {
+            tokens.push((span, token));
+        }
+    }
+    if let Some((_, Token::Punct(';'))) = tokens.last() {
+        tokens.pop();
+    }
+    let end = tokens.last()?.0.end;
+
+    let select = tokens.iter().position(|(_, token)| is(token, "select"))?;
+    if select > 0 && !is(&tokens[0].1, "with") {
+        return None;
+    }
+    let body = &tokens[select..];
+    if body.iter().any(|(_, token)| {
+        matches!(token, Token::Punct(';'))
+            || ["into", "for", "option"]
+                .iter()
+                .any(|keyword| is(token, keyword))
+    }) {
+        return None;
+    }
+
+    let mut inner_end = end;
+    let mut order = Vec::new();
+    let order_by = body
+        .windows(2)
+        .rposition(|w| is(&w[0].1, "order") && is(&w[1].1, "by"));
+    if let Some(start) = order_by {
+        let items = &body[start + 2..];
+        let offset = items.iter().position(|(_, token)| is(token, "offset"));
+        for item in
+            items[..offset.unwrap_or(items.len())].split(|(_, token)| *token == Token::Punct(','))
+        {
+            let (item, direction) = match item {
+                [item @ .., (_, Token::Word(word))] if is_direction(word) => {
+                    (item, format!(" {word}"))
+                }
+                item => (item, String::new()),
+            };
+            let name = match item {
+                [(span, Token::Literal)] => {
+                    let n = sql[span.clone()].parse::<usize>().ok()?;
+                    &columns.get(n.checked_sub(1)?)?.0
+                }
+                [(_, Token::Word(name) | Token::Quoted(name))] => {
+                    &columns
+                        .iter()
+                        .find(|(column, _)| column.eq_ignore_ascii_case(name))?
+                        .0
+                }
+                _ => return None,
+            };
+            order.push(format!("[q].{}{direction}", quoted(name)));
         }
 
-        // UUID
-        "UNIQUEIDENTIFIER" | "UUID" => Ok(JsonValue::String(value.as_str()?.to_string())),
+        // a derived table is only ordered to pick the rows it returns
+        let set_operation = body[..start].iter().any(|(_, token)| {
+            ["union", "except", "intersect"]
+                .iter()
+                .any(|keyword| is(token, keyword))
+        });
+        let modifier = body[1..]
+            .iter()
+            .find(|(_, token)| !is(token, "distinct") && !is(token, "all"));
+        let top = modifier.is_some_and(|(_, token)| is(token, "top")) && !set_operation;
+        if offset.is_none() && !top {
+            inner_end = body[start].0.start;
+        }
+    }
+
+    let select_list: Vec<String> = columns
+        .iter()
+        .map(|(name, column_type)| match column_type {
+            Some(column_type) => format!(
+                "{} AS {}",
+                column_type.conversion(&format!("[q].{}", quoted(name))),
+                quoted(name)
+            ),
+            None => format!("[q].{}", quoted(name)),
+        })
+        .collect();
+    let start = tokens[select].0.start;
+    let mut query = format!(
+        "{}SELECT {} FROM ({}) AS [q]",
+        &sql[..start],
+        select_list.join(", "),
+        sql[start..inner_end].trim_end()
+    );
+    if !order.is_empty() {
+        query.push_str(" ORDER BY ");
+        query.push_str(&order.join(", "));
+    }
+    Some(query)
+}
+
+fn is(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+}
+
+fn is_direction(word: &str) -> bool {
+    word.eq_ignore_ascii_case("asc") || word.eq_ignore_ascii_case("desc")
+}
+
+/// Quotes `name` as an identifier.
+fn quoted(name: &str) -> String {
+    format!("[{}]", name.replace(']', "]]"))
+}
+
+/// Decodes a value of a column of type `column_type`, which is read as a string when
+/// the column is converted by the server.
+pub(crate) fn to_json(
+    v: MssqlValueRef,
+    column_type: MssqlType,
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
+    if v.is_null() {
+        return Ok(JsonValue::Null);
+    }
+
+    let is_text = matches!(
+        column_type,
+        MssqlType::Char | MssqlType::VarChar | MssqlType::NChar | MssqlType::NVarChar
+    ) || column_type.is_converted();
+    let codec = options.codecs.get(column_type.name());
+    if let Some(codec) = codec.filter(|_| column_type != MssqlType::Unsupported) {
+        if is_text {
+            return match <String as Decode<Mssql>>::decode(v) {
+                Ok(text) => super::custom(codec, Ok(RawValue::Text(&text)), options),
+                Err(e) => super::custom(codec, Err(e), options),
+            };
+        }
+        return match le_bytes(v, column_type) {
+            Ok(bytes) => super::custom(codec, Ok(RawValue::Binary(&bytes)), options),
+            Err(e) => super::custom(codec, Err(e), options),
+        };
+    }
+
+    if column_type.is_converted() {
+        return match <String as Decode<Mssql>>::decode(v) {
+            Ok(text) => converted(column_type, &text, options),
+            Err(e) => super::failed(e, options),
+        };
+    }
+
+    let res = match column_type {
+        MssqlType::TinyInt => match <u8 as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::Number(v.into()),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::SmallInt => match <i16 as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::Number(v.into()),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Int => match <i32 as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::Number(v.into()),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::BigInt => match <i64 as Decode<Mssql>>::decode(v) {
+            Ok(v) => super::int(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Real => match <f32 as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Float => match <f64 as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Bit => match <bool as Decode<Mssql>>::decode(v) {
+            Ok(v) => JsonValue::Bool(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Char | MssqlType::VarChar | MssqlType::NChar | MssqlType::NVarChar => {
+            match <String as Decode<Mssql>>::decode(v) {
+                Ok(v) => JsonValue::String(v),
+                Err(e) => super::failed(e, options)?,
+            }
+        }
+        MssqlType::Null => JsonValue::Null,
+        _ => {
+            return Err(Error::UnsupportedDatatype(format!(
+                "{:?}, which sqlx can't read from MSSQL; CAST it to NVARCHAR in the query",
+                v.type_info()
+            )))
+        }
+    };
+
+    Ok(res)
+}
+
+/// Decodes `text`, the string a value of type `column_type` is converted to by the server.
+fn converted(
+    column_type: MssqlType,
+    text: &str,
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
+    let res = match column_type {
+        MssqlType::Decimal | MssqlType::Numeric | MssqlType::Money | MssqlType::SmallMoney => {
+            match text.parse::<BigDecimal>() {
+                Ok(v) => super::decimal(v, options.big_numbers),
+                Err(e) => super::failed(e, options)?,
+            }
+        }
+        MssqlType::UniqueIdentifier => match Uuid::parse_str(text) {
+            Ok(v) => JsonValue::String(v.to_string()),
+            Err(e) => super::failed(e, options)?,
+        },
+        MssqlType::Binary | MssqlType::VarBinary => match hex::decode(text) {
+            Ok(v) => super::bytes(v, options.blobs),
+            Err(e) => super::failed(e, options)?,
+        },
+        _ => match temporal(column_type, text) {
+            Ok(v) => super::temporal(v, options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+    };
+
+    Ok(res)
+}
+
+/// Reads a date or time in the ISO 8601 form the server converts it to, such as
+/// `2024-01-31 13:45:00.1234567 +01:00`.
+fn temporal(column_type: MssqlType, text: &str) -> Result<Temporal, Parse> {
+    Ok(match column_type {
+        MssqlType::Date => Temporal::Date(Date::parse(text, DATE)?),
+        MssqlType::Time => Temporal::Time(Time::parse(text, TIME)?),
+        MssqlType::DateTimeOffset => {
+            let (date_time, offset) = text.rsplit_once(' ').unwrap_or((text, ""));
+            Temporal::Timestamp(
+                PrimitiveDateTime::parse(date_time, DATE_TIME)?
+                    .assume_offset(UtcOffset::parse(offset, OFFSET)?),
+            )
+        }
+        _ => Temporal::DateTime(PrimitiveDateTime::parse(text, DATE_TIME)?),
+    })
+}
+
+/// Returns the little-endian TDS bytes of a fixed-size value.
+///
+/// sqlx keeps the bytes of values private, they are encoded again from the decoded value.
+fn le_bytes(v: MssqlValueRef, column_type: MssqlType) -> Result<Vec<u8>, BoxDynError> {
+    Ok(match column_type {
+        MssqlType::TinyInt => vec![<u8 as Decode<Mssql>>::decode(v)?],
+        MssqlType::SmallInt => <i16 as Decode<Mssql>>::decode(v)?.to_le_bytes().to_vec(),
+        MssqlType::Int => <i32 as Decode<Mssql>>::decode(v)?.to_le_bytes().to_vec(),
+        MssqlType::BigInt => <i64 as Decode<Mssql>>::decode(v)?.to_le_bytes().to_vec(),
+        MssqlType::Real => <f32 as Decode<Mssql>>::decode(v)?.to_le_bytes().to_vec(),
+        MssqlType::Float => <f64 as Decode<Mssql>>::decode(v)?.to_le_bytes().to_vec(),
+        MssqlType::Bit => vec![u8::from(<bool as Decode<Mssql>>::decode(v)?)],
+        other => return Err(format!("{} values can't be read as bytes", other.name()).into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn of<T: Type<Mssql>>() -> MssqlType {
+        MssqlType::of(&T::type_info())
+    }
+
+    #[test]
+    fn readable_types_are_classified() {
+        assert_eq!(of::<u8>(), MssqlType::TinyInt);
+        assert_eq!(of::<i8>(), MssqlType::TinyInt);
+        assert_eq!(of::<i16>(), MssqlType::SmallInt);
+        assert_eq!(of::<i32>(), MssqlType::Int);
+        assert_eq!(of::<i64>(), MssqlType::BigInt);
+        assert_eq!(of::<f32>(), MssqlType::Real);
+        assert_eq!(of::<f64>(), MssqlType::Float);
+        assert_eq!(of::<bool>(), MssqlType::Bit);
+        assert_eq!(of::<String>(), MssqlType::NVarChar);
+        assert_eq!(of::<&str>(), MssqlType::NVarChar);
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(of::<i64>().name(), "BIGINT");
+        assert_eq!(of::<bool>().name(), "BIT");
+        assert_eq!(of::<String>().name(), "NVARCHAR");
+        assert_eq!(MssqlType::Unsupported.name(), "UNKNOWN");
+    }
+
+    #[test]
+    fn described_types_are_converted() {
+        let converted = MssqlType::converted;
+        assert_eq!(converted("datetime2(7)"), Some(MssqlType::DateTime2));
+        assert_eq!(
+            converted("datetimeoffset(3)"),
+            Some(MssqlType::DateTimeOffset)
+        );
+        assert_eq!(converted("decimal(10,2)"), Some(MssqlType::Decimal));
+        assert_eq!(converted("money"), Some(MssqlType::Money));
+        assert_eq!(
+            converted("uniqueidentifier"),
+            Some(MssqlType::UniqueIdentifier)
+        );
+        assert_eq!(converted("varbinary(2000)"), Some(MssqlType::VarBinary));
+        assert_eq!(converted("varbinary(2001)"), None);
+        assert_eq!(converted("varbinary(max)"), None);
+        assert_eq!(converted("int"), None);
+        assert_eq!(converted("nvarchar(50)"), None);
+    }
+
+    fn wrapped(sql: &str) -> Option<String> {
+        let columns = [
+            ("id".to_string(), None),
+            ("created".to_string(), Some(MssqlType::DateTime)),
+            ("total".to_string(), Some(MssqlType::Money)),
+        ];
+        readable_query(sql, &columns)
+    }
+
+    #[test]
+    fn queries_are_wrapped_to_convert_columns() {
+        let select = "SELECT [q].[id], CONVERT(NVARCHAR(40), [q].[created], 121) AS [created], \
+            CONVERT(NVARCHAR(40), [q].[total], 2) AS [total]";
+        assert_eq!(
+            wrapped("SELECT id, created, total FROM orders WHERE id > @p1;").unwrap(),
+            format!("{select} FROM (SELECT id, created, total FROM orders WHERE id > @p1) AS [q]")
+        );
+        assert_eq!(
+            wrapped("WITH recent AS (SELECT * FROM orders ORDER BY id OFFSET 0 ROWS) SELECT * FROM recent")
+                .unwrap(),
+            format!(
+                "WITH recent AS (SELECT * FROM orders ORDER BY id OFFSET 0 ROWS) \
+                 {select} FROM (SELECT * FROM recent) AS [q]"
+            )
+        );
+        // nested orders stay where they are
+        assert_eq!(
+            wrapped(
+                "SELECT ROW_NUMBER() OVER (ORDER BY created) AS id, created, total FROM orders"
+            )
+            .unwrap(),
+            format!(
+                "{select} FROM (SELECT ROW_NUMBER() OVER (ORDER BY created) AS id, created, \
+                 total FROM orders) AS [q]"
+            )
+        );
+
+        let columns = [("a]b".to_string(), Some(MssqlType::UniqueIdentifier))];
+        assert_eq!(
+            readable_query("SELECT id AS [a]]b] FROM t", &columns).unwrap(),
+            "SELECT CONVERT(NVARCHAR(40), [q].[a]]b]) AS [a]]b] FROM (SELECT id AS [a]]b] FROM t) AS [q]"
+        );
+    }
+
+    #[test]
+    fn orders_are_moved_out_of_the_wrapped_query() {
+        let select = "SELECT [q].[id], CONVERT(NVARCHAR(40), [q].[created], 121) AS [created], \
+            CONVERT(NVARCHAR(40), [q].[total], 2) AS [total]";
+        assert_eq!(
+            wrapped("SELECT * FROM orders ORDER BY Created DESC, [total], 1 asc").unwrap(),
+            format!(
+                "{select} FROM (SELECT * FROM orders) AS [q] \
+                 ORDER BY [q].[created] DESC, [q].[total], [q].[id] asc"
+            )
+        );
+        assert_eq!(
+            wrapped("SELECT * FROM a UNION SELECT * FROM b ORDER BY id").unwrap(),
+            format!(
+                "{select} FROM (SELECT * FROM a UNION SELECT * FROM b) AS [q] ORDER BY [q].[id]"
+            )
+        );
+        // the order picking the rows is kept
+        assert_eq!(
+            wrapped("SELECT DISTINCT TOP 5 * FROM orders ORDER BY id").unwrap(),
+            format!(
+                "{select} FROM (SELECT DISTINCT TOP 5 * FROM orders ORDER BY id) AS [q] \
+                 ORDER BY [q].[id]"
+            )
+        );
+        assert_eq!(
+            wrapped("SELECT * FROM orders ORDER BY id OFFSET @p1 ROWS FETCH NEXT 10 ROWS ONLY")
+                .unwrap(),
+            format!(
+                "{select} FROM (SELECT * FROM orders ORDER BY id OFFSET @p1 ROWS FETCH NEXT 10 \
+                 ROWS ONLY) AS [q] ORDER BY [q].[id]"
+            )
+        );
+    }
+
+    #[test]
+    fn queries_that_cant_be_wrapped() {
+        for sql in [
+            "EXEC report",
+            "UPDATE orders SET total = 0 OUTPUT INSERTED.*",
+            "SELECT * INTO copy FROM orders",
+            "SELECT * FROM orders FOR JSON PATH",
+            "SELECT * FROM orders OPTION (RECOMPILE)",
+            "SELECT * FROM orders; SELECT * FROM orders",
+            // the output columns can't be told from the source ones
+            "SELECT * FROM orders o ORDER BY o.created",
+            "SELECT * FROM orders ORDER BY LEN(note)",
+            "SELECT * FROM orders ORDER BY 4",
+        ] {
+            assert_eq!(wrapped(sql), None, "{sql}");
+        }
+
+        let columns = |names: [&str; 2]| names.map(|name| (name.to_string(), None));
+        assert_eq!(
+            readable_query("SELECT a, A FROM t", &columns(["a", "A"])),
+            None
+        );
+        assert_eq!(
+            readable_query("SELECT a, 1 FROM t", &columns(["a", ""])),
+            None
+        );
+    }
+
+    fn converted(column_type: MssqlType, text: &str) -> JsonValue {
+        let options = DecodeOptions::default();
+        super::converted(column_type, text, &options).unwrap()
+    }
+
+    #[test]
+    fn converted_dates_and_times() {
+        use MssqlType::*;
+        assert_eq!(converted(Date, "2024-01-02"), "2024-01-02");
+        assert_eq!(converted(Time, "13:45:00.5000000"), "13:45:00.5");
+        assert_eq!(
+            converted(DateTime, "2024-01-02 03:04:05.123"),
+            "2024-01-02T03:04:05.123"
+        );
+        assert_eq!(
+            converted(SmallDateTime, "2024-01-02 03:04:00.000"),
+            "2024-01-02T03:04:00"
+        );
+        assert_eq!(
+            converted(DateTime2, "2024-01-02 03:04:05.1234567"),
+            "2024-01-02T03:04:05.1234567"
+        );
+        assert_eq!(
+            converted(DateTimeOffset, "2024-01-02 03:04:05.1234567 -05:30"),
+            "2024-01-02T03:04:05.1234567-05:30"
+        );
+        assert_eq!(
+            converted(DateTimeOffset, "2024-01-02 03:04:05 +00:00"),
+            "2024-01-02T03:04:05Z"
+        );
+    }
+
+    #[test]
+    fn converted_numbers_identifiers_and_bytes() {
+        use MssqlType::*;
+        assert_eq!(converted(Decimal, "-12.50"), "-12.50");
+        assert_eq!(
+            converted(Numeric, "12345678901234567890"),
+            "12345678901234567890"
+        );
+        assert_eq!(converted(Money, "1234.5600"), "1234.5600");
+        assert_eq!(
+            converted(UniqueIdentifier, "6F9619FF-8B86-D011-B42D-00C04FC964FF"),
+            "6f9619ff-8b86-d011-b42d-00c04fc964ff"
+        );
+        assert_eq!(
+            converted(VarBinary, "0102FF"),
+            serde_json::json!([1, 2, 255])
+        );
+
+        let options = DecodeOptions {
+            big_numbers: crate::row::BigNumbers::Number,
+            blobs: crate::row::BlobEncoding::Hex,
+            ..Default::default()
+        };
+        assert_eq!(
+            super::converted(SmallMoney, "12.5000", &options).unwrap(),
+            12.5
+        );
+        assert_eq!(
+            super::converted(Binary, "0102FF", &options).unwrap(),
+            "0102ff"
+        );
+    }
+
+    #[test]
+    fn unreadable_converted_values() {
+        let options = DecodeOptions::default();
+        let value = super::converted(MssqlType::DateTime2, "soon", &options);
+        assert_eq!(value.unwrap(), JsonValue::Null);
 
-        // Fallback for unknown or unhandled types
-        other => Err(Error::UnsupportedDatatype(other.to_string())),
+        let options = DecodeOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let value = super::converted(MssqlType::UniqueIdentifier, "soon", &options);
+        assert!(matches!(value, Err(Error::Decode { .. })));
     }
 }
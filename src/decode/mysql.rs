@@ -3,92 +3,164 @@
 // SPDX-License-Identifier: MIT
 
 use serde_json::Value as JsonValue;
-use sqlx::{mysql::MySqlValueRef, TypeInfo, Value, ValueRef};
+use sqlx::{
+    mysql::{MySql, MySqlTypeInfo, MySqlValueRef},
+    types::BigDecimal,
+    Decode, TypeInfo, Value, ValueRef,
+};
 use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
 
-use crate::Error;
+use crate::{
+    codec::RawValue,
+    decode::Temporal,
+    geometry::Geometry,
+    row::{BigNumbers, DecodeOptions},
+    Error,
+};
 
-pub(crate) fn to_json(v: MySqlValueRef) -> Result<JsonValue, Error> {
+pub(crate) fn to_json(v: MySqlValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
+    if let Some(codec) = options.codecs.get(v.type_info().name()) {
+        let value = <&[u8] as Decode<MySql>>::decode(v).map(RawValue::Binary);
+        return super::custom(codec, value, options);
+    }
+
+    // sqlx names `SET` columns by their storage type, `CHAR`
+    if is_set(&v.type_info()) {
+        return set(v, options);
+    }
+
     let res = match v.type_info().name() {
         "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::String(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "FLOAT" | "DOUBLE" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<f64>() {
-                JsonValue::from(v)
-            } else {
-                JsonValue::Null
+            match ValueRef::to_owned(&v).try_decode() {
+                Ok(v) => JsonValue::String(v),
+                Err(e) => super::failed(e, options)?,
             }
         }
+        "FLOAT" | "DOUBLE" => match ValueRef::to_owned(&v).try_decode::<f64>() {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
         "TINYINT" | "SMALLINT" | "INT" | "MEDIUMINT" | "BIGINT" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i64>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
+            match ValueRef::to_owned(&v).try_decode::<i64>() {
+                Ok(v) => super::int(v, options.big_numbers),
+                Err(e) => super::failed(e, options)?,
             }
         }
         "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "INT UNSIGNED" | "MEDIUMINT UNSIGNED"
-        | "BIGINT UNSIGNED" | "YEAR" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<u64>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "BOOLEAN" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::Bool(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "DATE" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Date>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "TIME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Time>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "DATETIME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "TIMESTAMP" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "JSON" => ValueRef::to_owned(&v).try_decode().unwrap_or_default(),
-        "TINIYBLOB" | "MEDIUMBLOB" | "BLOB" | "LONGBLOB" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
-                JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
-            } else {
-                JsonValue::Null
+        | "BIGINT UNSIGNED" | "YEAR" => match ValueRef::to_owned(&v).try_decode::<u64>() {
+            Ok(v) => super::uint(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        "DECIMAL" => match ValueRef::to_owned(&v).try_decode::<BigDecimal>() {
+            Ok(v) => super::decimal(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        // always a number, never bytes, whatever the width of the column
+        "BIT" => match <&[u8] as Decode<MySql>>::decode(v) {
+            Ok(v) if v.len() <= 8 => bit(v, options.big_numbers),
+            Ok(v) => super::failed(format!("BIT value of {} bytes", v.len()), options)?,
+            Err(e) => super::failed(e, options)?,
+        },
+        "SET" => set(v, options)?,
+        "BOOLEAN" => match ValueRef::to_owned(&v).try_decode() {
+            Ok(v) => JsonValue::Bool(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        "DATE" => match ValueRef::to_owned(&v).try_decode::<Date>() {
+            Ok(v) => super::temporal(Temporal::Date(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "TIME" => match ValueRef::to_owned(&v).try_decode::<Time>() {
+            Ok(v) => super::temporal(Temporal::Time(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "DATETIME" => match ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
+            Ok(v) => super::temporal(Temporal::DateTime(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "TIMESTAMP" => match ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
+            Ok(v) => super::temporal(Temporal::Timestamp(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "JSON" => match ValueRef::to_owned(&v).try_decode() {
+            Ok(v) => v,
+            Err(e) => super::failed(e, options)?,
+        },
+        "TINYBLOB" | "MEDIUMBLOB" | "BLOB" | "LONGBLOB" | "BINARY" | "VARBINARY" => {
+            match ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
+                Ok(v) => super::bytes(v, options.blobs),
+                Err(e) => super::failed(e, options)?,
             }
         }
+        // the SRID as 4 little-endian bytes followed by the WKB of the geometry
+        "GEOMETRY" => match <&[u8] as Decode<MySql>>::decode(v) {
+            Ok(v) => match Geometry::from_mysql(v) {
+                Ok(geometry) => geometry.to_geojson(),
+                Err(e) => super::failed(e, options)?,
+            },
+            Err(e) => super::failed(e, options)?,
+        },
         "NULL" => JsonValue::Null,
         _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
     };
 
     Ok(res)
 }
+
+/// Whether the column has the `SET` flag, which sqlx only exposes through `Debug`.
+fn is_set(type_info: &MySqlTypeInfo) -> bool {
+    format!("{type_info:?}")
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .any(|flag| flag == "SET")
+}
+
+/// Decodes a `SET` value, sent as its members joined by commas, into an array.
+fn set(v: MySqlValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    match <&str as Decode<MySql>>::decode(v) {
+        Ok(members) => Ok(set_members(members)),
+        Err(e) => super::failed(e, options),
+    }
+}
+
+fn set_members(members: &str) -> JsonValue {
+    match members {
+        "" => JsonValue::Array(Vec::new()),
+        members => members.split(',').map(JsonValue::from).collect(),
+    }
+}
+
+/// Returns a `BIT(n)` value, sent as big-endian bytes, as an unsigned integer.
+fn bit(bytes: &[u8], big_numbers: BigNumbers) -> JsonValue {
+    let n = bytes.iter().fold(0u64, |n, b| n << 8 | u64::from(*b));
+    super::uint(n, big_numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn set_members_are_an_array() {
+        assert_eq!(set_members(""), json!([]));
+        assert_eq!(set_members("a"), json!(["a"]));
+        assert_eq!(set_members("a,b c,d"), json!(["a", "b c", "d"]));
+    }
+
+    #[test]
+    fn bits_are_numbers() {
+        assert_eq!(bit(&[], BigNumbers::String), json!(0));
+        assert_eq!(bit(&[0b101], BigNumbers::String), json!(5));
+        assert_eq!(bit(&[1, 0], BigNumbers::String), json!(256));
+        assert_eq!(
+            bit(&[0xff; 8], BigNumbers::String),
+            json!("18446744073709551615")
+        );
+        assert_eq!(bit(&[0xff; 8], BigNumbers::Number), json!(u64::MAX));
+    }
+}
@@ -2,85 +2,489 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use serde_json::Value as JsonValue;
-use sqlx::{postgres::PgValueRef, TypeInfo, Value, ValueRef};
+use serde_json::{Map, Value as JsonValue};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{
+        types::{PgInterval, PgMoney, PgRange, PgRecordDecoder},
+        PgTypeInfo, PgTypeKind, PgValue, PgValueFormat, PgValueRef, Postgres,
+    },
+    types::{BigDecimal, Uuid},
+    Decode, Type, TypeInfo, Value, ValueRef,
+};
 use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
 
-use crate::Error;
+use std::{fmt::Write, net::IpAddr, ops::Bound};
 
-pub(crate) fn to_json(v: PgValueRef) -> Result<JsonValue, Error> {
+use crate::{
+    codec::RawValue,
+    decode::Temporal,
+    geometry::Geometry,
+    row::{BigNumbers, DecodeOptions},
+    Error,
+};
+
+pub(crate) fn to_json(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
-    let res = match v.type_info().name() {
-        "CHAR" | "VARCHAR" | "TEXT" | "NAME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::String(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "FLOAT4" | "FLOAT8" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<f64>() {
-                JsonValue::from(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "INT2" | "INT4" | "INT8" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i64>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "BOOL" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::Bool(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "DATE" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Date>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
+    if let Some(codec) = options.codecs.get(v.type_info().name()) {
+        let value = match v.format() {
+            PgValueFormat::Binary => v.as_bytes().map(RawValue::Binary),
+            PgValueFormat::Text => v.as_str().map(RawValue::Text),
+        };
+        return super::custom(codec, value, options);
+    }
+
+    match v.type_info().kind() {
+        PgTypeKind::Array(_) => return array(v, options),
+        PgTypeKind::Range(_) => return range(v, options),
+        PgTypeKind::Composite(_) => return composite(v, options),
+        PgTypeKind::Enum(_) => {
+            return match v.as_str() {
+                Ok(label) => Ok(JsonValue::String(label.to_string())),
+                Err(e) => super::failed(e, options),
             }
         }
-        "TIME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Time>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+        _ => {}
+    }
+
+    let res = match v.type_info().name() {
+        "CHAR" | "VARCHAR" | "TEXT" | "NAME" => match ValueRef::to_owned(&v).try_decode() {
+            Ok(v) => JsonValue::String(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        // sqlx only decodes each width of integers and floats into its own Rust type
+        "FLOAT4" => match ValueRef::to_owned(&v).try_decode::<f32>() {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        "FLOAT8" => match ValueRef::to_owned(&v).try_decode::<f64>() {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        "INT2" => match ValueRef::to_owned(&v).try_decode::<i16>() {
+            Ok(v) => JsonValue::Number(v.into()),
+            Err(e) => super::failed(e, options)?,
+        },
+        "INT4" => match ValueRef::to_owned(&v).try_decode::<i32>() {
+            Ok(v) => JsonValue::Number(v.into()),
+            Err(e) => super::failed(e, options)?,
+        },
+        "INT8" => match ValueRef::to_owned(&v).try_decode::<i64>() {
+            Ok(v) => super::int(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        "NUMERIC" => match ValueRef::to_owned(&v).try_decode::<BigDecimal>() {
+            Ok(v) => super::decimal(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        "BOOL" => match ValueRef::to_owned(&v).try_decode() {
+            Ok(v) => JsonValue::Bool(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        "DATE" => match ValueRef::to_owned(&v).try_decode::<Date>() {
+            Ok(v) => super::temporal(Temporal::Date(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "TIME" => match ValueRef::to_owned(&v).try_decode::<Time>() {
+            Ok(v) => super::temporal(Temporal::Time(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "TIMESTAMP" => match ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
+            Ok(v) => super::temporal(Temporal::DateTime(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "TIMESTAMPTZ" => match ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
+            Ok(v) => super::temporal(Temporal::Timestamp(v), options.date_times),
+            Err(e) => super::failed(e, options)?,
+        },
+        "JSON" | "JSONB" => match ValueRef::to_owned(&v).try_decode() {
+            Ok(v) => v,
+            Err(e) => super::failed(e, options)?,
+        },
+        "BYTEA" => match ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
+            Ok(v) => super::bytes(v, options.blobs),
+            Err(e) => super::failed(e, options)?,
+        },
+        "MONEY" => match ValueRef::to_owned(&v).try_decode::<PgMoney>() {
+            Ok(v) => money(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        "UUID" => match ValueRef::to_owned(&v).try_decode::<Uuid>() {
+            Ok(v) => JsonValue::String(v.to_string()),
+            Err(e) => super::failed(e, options)?,
+        },
+        "INTERVAL" => match ValueRef::to_owned(&v).try_decode::<PgInterval>() {
+            Ok(v) => JsonValue::String(interval(&v)),
+            Err(e) => super::failed(e, options)?,
+        },
+        "INET" | "CIDR" => match v.format() {
+            PgValueFormat::Binary => match v.as_bytes().ok().and_then(inet) {
+                Some(address) => JsonValue::String(address),
+                None => super::failed("invalid network address", options)?,
+            },
+            PgValueFormat::Text => text(v, options)?,
+        },
+        "MACADDR" | "MACADDR8" => match v.format() {
+            PgValueFormat::Binary => match v.as_bytes() {
+                Ok(bytes) => macaddr(bytes),
+                Err(e) => super::failed(e, options)?,
+            },
+            PgValueFormat::Text => text(v, options)?,
+        },
+        // PostGIS types
+        "geometry" | "geography" => geometry(v, options)?,
+        "RECORD" => record(v, options)?,
+        "VOID" => JsonValue::Null,
+        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+    };
+
+    Ok(res)
+}
+
+/// An element of an array, range or record, read by the sqlx decoder of its container
+/// and then decoded with [`to_json`] using its own type.
+struct Element(PgValue);
+
+impl Type<Postgres> for Element {
+    // only used for the elements of arrays in the text format, which have no type
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(_: &PgTypeInfo) -> bool {
+        true
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Element {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(Self(ValueRef::to_owned(&value)))
+    }
+}
+
+impl Element {
+    fn to_json(&self, options: &DecodeOptions) -> Result<JsonValue, Error> {
+        to_json(self.0.as_ref(), options)
+    }
+}
+
+/// Decodes a one-dimensional array, arrays of more dimensions fail to decode.
+fn array(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    let elements = match <Vec<Element> as Decode<Postgres>>::decode(v) {
+        Ok(elements) => elements,
+        Err(e) => return super::failed(e, options),
+    };
+    elements
+        .iter()
+        .map(|element| element.to_json(options))
+        .collect()
+}
+
+/// Decodes a range as `{ lower, upper, bounds }`, with `null` for an unbounded side
+/// and bounds such as `"[)"`, or `"empty"` for an empty range.
+fn range(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    const EMPTY: u8 = 0x01;
+
+    let empty = match v.format() {
+        PgValueFormat::Binary => v.as_bytes().ok().and_then(|b| b.first()) == Some(&EMPTY),
+        PgValueFormat::Text => v.as_str().ok() == Some("empty"),
+    };
+    if empty {
+        return Ok(serde_json::json!({ "lower": null, "upper": null, "bounds": "empty" }));
+    }
+
+    let range = match <PgRange<Element> as Decode<Postgres>>::decode(v) {
+        Ok(range) => range,
+        Err(e) => return super::failed(e, options),
+    };
+    let (lower, open) = match &range.start {
+        Bound::Included(v) => (v.to_json(options)?, '['),
+        Bound::Excluded(v) => (v.to_json(options)?, '('),
+        Bound::Unbounded => (JsonValue::Null, '('),
+    };
+    let (upper, close) = match &range.end {
+        Bound::Included(v) => (v.to_json(options)?, ']'),
+        Bound::Excluded(v) => (v.to_json(options)?, ')'),
+        Bound::Unbounded => (JsonValue::Null, ')'),
+    };
+
+    Ok(serde_json::json!({
+        "lower": lower,
+        "upper": upper,
+        "bounds": format!("{open}{close}"),
+    }))
+}
+
+/// Decodes a value of a composite type into an object keyed by its field names.
+fn composite(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    let PgTypeKind::Composite(fields) = v.type_info().kind().clone() else {
+        return Ok(JsonValue::Null);
+    };
+    let mut decoder = match PgRecordDecoder::new(v) {
+        Ok(decoder) => decoder,
+        Err(e) => return super::failed(e, options),
+    };
+
+    let mut object = Map::new();
+    for (name, _) in fields.iter() {
+        let value = match decoder.try_decode::<Element>() {
+            Ok(element) => element.to_json(options)?,
+            Err(e) => super::failed(e, options)?,
+        };
+        object.insert(name.clone(), value);
+    }
+    Ok(JsonValue::Object(object))
+}
+
+/// Decodes an anonymous record, such as `ROW(1, 'a')`, into an array of its fields.
+fn record(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    let len = match v.format() {
+        PgValueFormat::Binary => match v.as_bytes() {
+            Ok([a, b, c, d, ..]) => u32::from_be_bytes([*a, *b, *c, *d]),
+            _ => return super::failed("truncated record", options),
+        },
+        // fields of records in the text format have no type
+        PgValueFormat::Text => return text(v, options),
+    };
+    let mut decoder = match PgRecordDecoder::new(v) {
+        Ok(decoder) => decoder,
+        Err(e) => return super::failed(e, options),
+    };
+
+    (0..len)
+        .map(|_| match decoder.try_decode::<Element>() {
+            Ok(element) => element.to_json(options),
+            Err(e) => super::failed(e, options),
+        })
+        .collect()
+}
+
+/// Returns a value in the text format as it was sent.
+fn text(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    match v.as_str() {
+        Ok(text) => Ok(JsonValue::from(text)),
+        Err(e) => super::failed(e, options),
+    }
+}
+
+/// Reads the EWKB of a PostGIS value, hexadecimal in the text format, as GeoJSON.
+fn geometry(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    let geometry = match v.format() {
+        PgValueFormat::Binary => v
+            .as_bytes()
+            .map_err(|e| e.to_string())
+            .and_then(Geometry::from_ewkb),
+        PgValueFormat::Text => v
+            .as_str()
+            .map_err(|e| e.to_string())
+            .and_then(|text| hex::decode(text).map_err(|e| e.to_string()))
+            .and_then(|ewkb| Geometry::from_ewkb(&ewkb)),
+    };
+    match geometry {
+        Ok(geometry) => Ok(geometry.to_geojson()),
+        Err(e) => super::failed(e, options),
+    }
+}
+
+/// Returns a `money` value as a decimal.
+fn money(v: PgMoney, big_numbers: BigNumbers) -> JsonValue {
+    // the scale of `money` depends on the server locale, 2 for most of them
+    super::decimal(v.to_bigdecimal(2), big_numbers)
+}
+
+/// Formats an interval as an ISO 8601 duration, like the `iso_8601` interval style.
+fn interval(v: &PgInterval) -> String {
+    const HOUR: i64 = 3_600_000_000;
+    const MINUTE: i64 = 60_000_000;
+    const SECOND: i64 = 1_000_000;
+
+    let mut s = String::from("P");
+    for (n, unit) in [(v.months / 12, 'Y'), (v.months % 12, 'M'), (v.days, 'D')] {
+        if n != 0 {
+            let _ = write!(s, "{n}{unit}");
         }
-        "TIMESTAMP" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+    }
+
+    let micros = v.microseconds;
+    if micros != 0 {
+        s.push('T');
+        let (hours, minutes) = (micros / HOUR, micros % HOUR / MINUTE);
+        let (seconds, fraction) = (micros % MINUTE / SECOND, micros % SECOND);
+        if hours != 0 {
+            let _ = write!(s, "{hours}H");
         }
-        "TIMESTAMPTZ" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
+        if minutes != 0 {
+            let _ = write!(s, "{minutes}M");
         }
-        "JSON" | "JSONB" => ValueRef::to_owned(&v).try_decode().unwrap_or_default(),
-        "BYTEA" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
-                JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
-            } else {
-                JsonValue::Null
-            }
+        if fraction != 0 {
+            let sign = if micros < 0 { "-" } else { "" };
+            let fraction = format!("{:06}", fraction.unsigned_abs());
+            let _ = write!(
+                s,
+                "{sign}{}.{}S",
+                seconds.unsigned_abs(),
+                fraction.trim_end_matches('0')
+            );
+        } else if seconds != 0 {
+            let _ = write!(s, "{seconds}S");
         }
-        "VOID" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+    }
+
+    if s == "P" {
+        s.push_str("T0S");
+    }
+    s
+}
+
+/// Formats an `inet` or `cidr` in the binary format: family, netmask bits, is_cidr,
+/// address length and address.
+fn inet(bytes: &[u8]) -> Option<String> {
+    const AF_INET: u8 = 2;
+    const AF_INET6: u8 = 3;
+
+    let [family, bits, is_cidr, _, address @ ..] = bytes else {
+        return None;
+    };
+    let (address, max_bits) = match *family {
+        AF_INET => (IpAddr::from(<[u8; 4]>::try_from(address).ok()?), 32),
+        AF_INET6 => (IpAddr::from(<[u8; 16]>::try_from(address).ok()?), 128),
+        _ => return None,
     };
 
-    Ok(res)
+    // `inet` hosts are written without their netmask
+    Some(if *is_cidr == 0 && *bits == max_bits {
+        address.to_string()
+    } else {
+        format!("{address}/{bits}")
+    })
+}
+
+/// Formats a `macaddr` or `macaddr8` in the binary format, e.g. `08:00:2b:01:02:03`.
+fn macaddr(bytes: &[u8]) -> JsonValue {
+    let octets: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    JsonValue::String(octets.join(":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::{Connection, PgConnection, Row};
+
+    use super::*;
+
+    /// Runs `setup` then decodes the first row of `sql` on the server at `POSTGRES_URL`,
+    /// or returns `None` when no server is given.
+    async fn decode(
+        setup: &[&str],
+        sql: &str,
+        options: &DecodeOptions,
+    ) -> Option<Vec<Result<JsonValue, Error>>> {
+        let url = std::env::var("POSTGRES_URL").ok()?;
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        for statement in setup {
+            sqlx::query(statement).execute(&mut conn).await.unwrap();
+        }
+        let row = sqlx::query(sql).fetch_one(&mut conn).await.unwrap();
+        let values = (0..row.len())
+            .map(|i| to_json(row.try_get_raw(i).unwrap(), options))
+            .collect();
+        Some(values)
+    }
+
+    #[tokio::test]
+    async fn integers_and_floats_of_every_width() {
+        let setup = ["CREATE TEMPORARY TABLE point (x int4, y int2, z float4)"];
+        let sql = "SELECT 1::int2, 2::int4, 3::int8, 1.5::float4, 2.5::float8,
+            ARRAY[1, 2, NULL]::int4[], ARRAY[1, 2]::int2[], ARRAY[0.5]::float4[],
+            int4range(1, 5), int8range(NULL, 5, '(]'), ROW(1, 2::int8),
+            ROW(1, 2, 0.5)::point";
+        let Some(values) = decode(&setup, sql, &DecodeOptions::default()).await else {
+            return;
+        };
+        let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            values,
+            [
+                json!(1),
+                json!(2),
+                json!(3),
+                json!(1.5),
+                json!(2.5),
+                json!([1, 2, null]),
+                json!([1, 2]),
+                json!([0.5]),
+                json!({ "lower": 1, "upper": 5, "bounds": "[)" }),
+                json!({ "lower": null, "upper": 6, "bounds": "()" }),
+                json!([1, 2]),
+                json!({ "x": 1, "y": 2, "z": 0.5 }),
+            ]
+        );
+    }
+
+    fn duration(months: i32, days: i32, microseconds: i64) -> String {
+        interval(&PgInterval {
+            months,
+            days,
+            microseconds,
+        })
+    }
+
+    #[tokio::test]
+    async fn strict_mode_reads_ordinary_columns() {
+        let setup = [
+            "CREATE TEMPORARY TABLE item (id serial, count integer, price real, tags int4[])",
+            "INSERT INTO item (count, price, tags) VALUES (3, 9.5, '{1,2}')",
+        ];
+        let options = DecodeOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let Some(values) = decode(&setup, "SELECT * FROM item", &options).await else {
+            return;
+        };
+        let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+        assert_eq!(values, [json!(1), json!(3), json!(9.5), json!([1, 2])]);
+    }
+
+    #[test]
+    fn intervals_are_iso_8601_durations() {
+        assert_eq!(duration(0, 0, 0), "PT0S");
+        assert_eq!(duration(14, 3, 0), "P1Y2M3D");
+        assert_eq!(duration(0, 0, 3_723_000_000), "PT1H2M3S");
+        assert_eq!(duration(0, 0, 1_500_000), "PT1.5S");
+        assert_eq!(duration(0, 0, 250), "PT0.00025S");
+        assert_eq!(duration(-1, -1, -1_500_000), "P-1M-1DT-1.5S");
+        assert_eq!(duration(0, -1, 7_200_000_000), "P-1DT2H");
+        assert_eq!(duration(0, 0, -60_000_000), "PT-1M");
+    }
+
+    #[test]
+    fn inet_and_cidr_addresses() {
+        assert_eq!(inet(&[2, 32, 0, 4, 192, 168, 0, 1]).unwrap(), "192.168.0.1");
+        assert_eq!(
+            inet(&[2, 24, 0, 4, 192, 168, 0, 1]).unwrap(),
+            "192.168.0.1/24"
+        );
+        assert_eq!(inet(&[2, 32, 1, 4, 10, 0, 0, 1]).unwrap(), "10.0.0.1/32");
+
+        let mut v6 = vec![3, 128, 0, 16, 0x20, 0x01, 0x0d, 0xb8];
+        v6.extend([0; 11]);
+        v6.push(1);
+        assert_eq!(inet(&v6).unwrap(), "2001:db8::1");
+        v6[1] = 64;
+        assert_eq!(inet(&v6).unwrap(), "2001:db8::1/64");
+
+        assert_eq!(inet(&[2, 32, 0, 4, 127, 0, 0]), None);
+        assert_eq!(inet(&[1, 32, 0, 4, 127, 0, 0, 1]), None);
+    }
+
+    #[test]
+    fn money_has_two_decimals() {
+        assert_eq!(money(PgMoney(1050), BigNumbers::String), "10.50");
+        assert_eq!(money(PgMoney(-5), BigNumbers::String), "-0.05");
+        assert_eq!(money(PgMoney(1050), BigNumbers::Number), 10.5);
+    }
 }
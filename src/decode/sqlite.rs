@@ -2,77 +2,290 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+use libsqlite3_sys as ffi;
 use serde_json::Value as JsonValue;
-use sqlx::{sqlite::SqliteValueRef, TypeInfo, Value, ValueRef};
-use time::{Date, PrimitiveDateTime, Time};
+use sqlx::{
+    sqlite::{Sqlite, SqliteConnection, SqliteTypeInfo, SqliteValueRef},
+    Decode, TypeInfo, Value, ValueRef,
+};
 
-use crate::Error;
+use std::{ffi::CStr, os::raw::c_int, ptr};
 
-pub(crate) fn to_json(v: SqliteValueRef) -> Result<JsonValue, Error> {
+use crate::{
+    codec::RawValue,
+    decode::Temporal,
+    geometry::Geometry,
+    row::{ColumnSource, DecodeOptions},
+    Error,
+};
+
+/// Decodes a value by its storage class, refined by the affinity of its column.
+///
+/// SQLite values carry their own storage class (`INTEGER`, `REAL`, `TEXT` or `BLOB`)
+/// whatever the declared type of their column, which is only a preference. sqlx maps
+/// declared types to an affinity following SQLite's rules (`VARCHAR(20)` is `TEXT`,
+/// `BIGINT` is `INTEGER`, `DOUBLE` is `REAL`), plus the `BOOLEAN`, `DATE`, `TIME` and
+/// `DATETIME` names. Expression columns and unknown declared types have the storage
+/// class of their first value.
+///
+/// Values of `DATE`, `TIME` and `DATETIME` columns are read as ISO 8601 strings, and as
+/// Unix timestamps for date-times stored as integers. Values in other forms keep the
+/// representation of their storage class.
+///
+/// Blobs of columns with an unrecognized type are read as GeoJSON when they are
+/// SpatiaLite geometries.
+///
+/// JSON is stored as text. The objects and arrays of columns declared as `JSON` or
+/// `JSONB` are parsed, as well as those returned by `json_*` functions with
+/// [`DecodeOptions::json_functions`].
+pub(crate) fn to_json(
+    v: SqliteValueRef,
+    column: &ColumnType,
+    source: ColumnSource,
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
-    let res = match v.type_info().name() {
-        "TEXT" => {
-            if let Ok(v) = v.to_owned().try_decode() {
-                JsonValue::String(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "REAL" => {
-            if let Ok(v) = v.to_owned().try_decode::<f64>() {
-                JsonValue::from(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "INTEGER" | "NUMERIC" => {
-            if let Ok(v) = v.to_owned().try_decode::<i64>() {
-                JsonValue::Number(v.into())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "BOOLEAN" => {
-            if let Ok(v) = v.to_owned().try_decode() {
-                JsonValue::Bool(v)
-            } else {
-                JsonValue::Null
-            }
-        }
-        "DATE" => {
-            if let Ok(v) = v.to_owned().try_decode::<Date>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "TIME" => {
-            if let Ok(v) = v.to_owned().try_decode::<Time>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
-            }
-        }
-        "DATETIME" => {
-            if let Ok(v) = v.to_owned().try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
-            } else {
-                JsonValue::Null
+    let column_type = &column.type_info;
+
+    // expression columns have the type of their value, like in the columnar format
+    let storage = v.type_info();
+    let type_name = if column_type.is_null() {
+        storage.name()
+    } else {
+        column_type.name()
+    };
+    if let Some(codec) = options.codecs.get(type_name) {
+        let value = if storage.name() == "BLOB" {
+            <&[u8] as Decode<Sqlite>>::decode(v).map(RawValue::Binary)
+        } else {
+            <&str as Decode<Sqlite>>::decode(v).map(RawValue::Text)
+        };
+        return super::custom(codec, value, options);
+    }
+
+    if let Some(value) = temporal(&v, column_type.name()) {
+        return Ok(super::temporal(value, options.date_times));
+    }
+
+    let res = match (v.type_info().name(), column_type.name()) {
+        ("INTEGER", "BOOLEAN") => match v.to_owned().try_decode::<bool>() {
+            Ok(v) => JsonValue::Bool(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        ("INTEGER", _) => match v.to_owned().try_decode::<i64>() {
+            Ok(v) => super::int(v, options.big_numbers),
+            Err(e) => super::failed(e, options)?,
+        },
+        ("REAL", _) => match v.to_owned().try_decode::<f64>() {
+            Ok(v) => JsonValue::from(v),
+            Err(e) => super::failed(e, options)?,
+        },
+        ("TEXT", _) => {
+            let json =
+                column.json || (source == ColumnSource::JsonFunction && options.json_functions);
+            match v.to_owned().try_decode::<String>() {
+                Ok(v) => json
+                    .then(|| json_document(&v))
+                    .flatten()
+                    .unwrap_or(JsonValue::String(v)),
+                Err(e) => super::failed(e, options)?,
             }
         }
-        "BLOB" => {
-            if let Ok(v) = v.to_owned().try_decode::<Vec<u8>>() {
-                JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
-            } else {
-                JsonValue::Null
+        ("BLOB", column_type) => match v.to_owned().try_decode::<Vec<u8>>() {
+            // SpatiaLite geometry types are unknown to sqlx
+            Ok(v) if column_type == "NULL" && Geometry::is_spatialite(&v) => {
+                match Geometry::from_spatialite(&v) {
+                    Ok(geometry) => geometry.to_geojson(),
+                    Err(e) => super::failed(e, options)?,
+                }
             }
-        }
-        "NULL" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+            Ok(v) => super::bytes(v, options.blobs),
+            Err(e) => super::failed(e, options)?,
+        },
+        ("NULL", _) => JsonValue::Null,
+        (other, _) => return Err(Error::UnsupportedDatatype(other.to_string())),
     };
 
     Ok(res)
 }
+
+/// The type of a SQLite column.
+pub(crate) struct ColumnType {
+    pub(crate) type_info: SqliteTypeInfo,
+    /// Whether the column is declared as `JSON` or `JSONB`, which sqlx maps to the
+    /// same type as untyped columns.
+    pub(crate) json: bool,
+}
+
+/// Returns whether each result column of the first statement of `sql` is declared as
+/// `JSON` or `JSONB`.
+///
+/// sqlx doesn't expose declared types, they are read from a statement prepared on the
+/// raw handle of `conn` and never run. A query that can't be prepared has no JSON
+/// column, its error is reported when it is executed.
+pub(crate) async fn json_columns(
+    conn: &mut SqliteConnection,
+    sql: &str,
+) -> Result<Vec<bool>, Error> {
+    let mut handle = conn.lock_handle().await?;
+    let db = handle.as_raw_handle().as_ptr();
+    let Ok(len) = c_int::try_from(sql.len()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut statement = ptr::null_mut();
+    // SAFETY: the handle is locked out of the worker thread of `conn` until it is dropped,
+    // and `sql` is `len` bytes long
+    let code = unsafe {
+        ffi::sqlite3_prepare_v2(
+            db,
+            sql.as_ptr().cast(),
+            len,
+            &mut statement,
+            ptr::null_mut(),
+        )
+    };
+    if code != ffi::SQLITE_OK || statement.is_null() {
+        return Ok(Vec::new());
+    }
+
+    // SAFETY: `statement` is a prepared statement, finalized once its columns are read
+    let columns = unsafe {
+        let columns = (0..ffi::sqlite3_column_count(statement))
+            .map(|i| {
+                let declared = ffi::sqlite3_column_decltype(statement, i);
+                !declared.is_null() && {
+                    let declared = CStr::from_ptr(declared).to_bytes();
+                    declared.eq_ignore_ascii_case(b"JSON")
+                        || declared.eq_ignore_ascii_case(b"JSONB")
+                }
+            })
+            .collect();
+        ffi::sqlite3_finalize(statement);
+        columns
+    };
+    Ok(columns)
+}
+
+/// Reads the value of a date or time column, `None` when it isn't in a format sqlx reads.
+fn temporal(v: &SqliteValueRef, column_type: &str) -> Option<Temporal> {
+    if !matches!(column_type, "DATE" | "TIME" | "DATETIME") {
+        return None;
+    }
+
+    let text = v.type_info().name() == "TEXT";
+    let v = v.to_owned();
+    match column_type {
+        "DATE" => v.try_decode_unchecked().ok().map(Temporal::Date),
+        "TIME" => v.try_decode_unchecked().ok().map(Temporal::Time),
+        // with an offset, or stored as a Unix timestamp
+        "DATETIME" => match v.try_decode_unchecked() {
+            Ok(v) => Some(Temporal::Timestamp(v)),
+            // sqlx panics on integers out of the range of Unix timestamps, such as
+            // milliseconds, which are returned as numbers
+            Err(_) if text => v.try_decode_unchecked().ok().map(Temporal::DateTime),
+            Err(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses `text` if it is a JSON object or array.
+fn json_document(text: &str) -> Option<JsonValue> {
+    text.trim_start()
+        .starts_with(['{', '['])
+        .then(|| serde_json::from_str(text).ok())
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::{Column, Connection, Row};
+
+    use super::*;
+    use crate::row::DateTimeFormat;
+
+    /// Runs `setup` then decodes the first row of `sql` on an in-memory database.
+    async fn decode(setup: &[&str], sql: &str, options: &DecodeOptions) -> Vec<JsonValue> {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        for statement in setup {
+            sqlx::query(statement).execute(&mut conn).await.unwrap();
+        }
+        let json = json_columns(&mut conn, sql).await.unwrap();
+        let row = sqlx::query(sql).fetch_one(&mut conn).await.unwrap();
+        row.columns()
+            .iter()
+            .map(|column| {
+                let column_type = ColumnType {
+                    type_info: column.type_info().clone(),
+                    json: json.get(column.ordinal()).copied().unwrap_or_default(),
+                };
+                let value = row.try_get_raw(column.ordinal()).unwrap();
+                to_json(value, &column_type, ColumnSource::Column, options).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn date_times_in_every_storage_class() {
+        let setup = [
+            "CREATE TABLE event (at DATETIME)",
+            "INSERT INTO event VALUES
+                ('2024-01-02 03:04:05'), ('2024-01-02T03:04:05+01:00'), (1704164645),
+                (1704164645000), (-99999999999999), (2.5), ('soon')",
+        ];
+        let options = DecodeOptions {
+            date_times: DateTimeFormat::Rfc3339,
+            ..Default::default()
+        };
+        let mut values = Vec::new();
+        for rowid in 1..=7 {
+            let sql = format!("SELECT at FROM event WHERE rowid = {rowid}");
+            values.extend(decode(&setup, &sql, &options).await);
+        }
+        assert_eq!(
+            values,
+            [
+                json!("2024-01-02T03:04:05"),
+                json!("2024-01-02T03:04:05+01:00"),
+                json!("2024-01-02T03:04:05Z"),
+                // milliseconds and other integers out of range are kept as numbers
+                json!(1704164645000_i64),
+                json!(-99999999999999_i64),
+                json!(2.5),
+                json!("soon"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn values_by_storage_class_and_affinity() {
+        let setup = [
+            "CREATE TABLE item (flag BOOLEAN, count BIGINT, ratio DOUBLE, name VARCHAR(20),
+                data BLOB, doc JSON, day DATE, other)",
+            "INSERT INTO item VALUES (1, 9007199254740993, 0.5, 'a', x'0102', '{\"a\":[1]}',
+                '2024-01-02', '[1]')",
+        ];
+        let options = DecodeOptions::default();
+        let values = decode(&setup, "SELECT *, json('[2]') FROM item", &options).await;
+        assert_eq!(
+            values,
+            [
+                json!(true),
+                json!("9007199254740993"),
+                json!(0.5),
+                json!("a"),
+                json!([1, 2]),
+                json!({ "a": [1] }),
+                json!("2024-01-02"),
+                // only declared JSON columns are parsed
+                json!("[1]"),
+                json!("[2]"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,575 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde::{Serialize, Serializer};
+use sqlx::error::DatabaseError;
+#[cfg(feature = "mssql")]
+use sqlx::mssql::MssqlDatabaseError;
+#[cfg(feature = "mysql")]
+use sqlx::mysql::MySqlDatabaseError;
+#[cfg(feature = "postgres")]
+use sqlx::postgres::PgDatabaseError;
+#[cfg(feature = "sqlite")]
+use sqlx::sqlite::SqliteError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+    #[error(transparent)]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error("invalid connection url: {0}")]
+    InvalidDbUrl(String),
+    #[error("database {0} not loaded")]
+    DatabaseNotLoaded(String),
+    #[error("transaction {0} not found")]
+    TransactionNotFound(String),
+    #[error("cursor {0} not found")]
+    CursorNotFound(String),
+    #[error("statement {index} of the batch failed: {source}")]
+    BatchStatement { index: usize, source: Box<Error> },
+    #[error("migrations are not supported by the {0} driver")]
+    MigrationsNotSupported(&'static str),
+    #[error("invalid bind value: {0}")]
+    InvalidBindValue(String),
+    #[error("no value given for the parameter {0}")]
+    MissingParameter(String),
+    #[error("named parameters not used by the query: {0}")]
+    UnusedParameters(String),
+    #[error("duplicate column {0} in the result set")]
+    DuplicateColumn(String),
+    #[error("failed to encode the result: {0}")]
+    Encode(String),
+    #[error("unsupported datatype: {0}")]
+    UnsupportedDatatype(String),
+    #[error("failed to decode column {column} of type {type_name} in row {row}: {message}")]
+    Decode {
+        column: String,
+        type_name: String,
+        row: usize,
+        message: String,
+    },
+}
+
+/// The category of an [`Error`], sent to the frontend as the `kind` of its payload.
+///
+/// The constraint, syntax, lock and undefined object kinds are read from the error code
+/// of the database. MSSQL errors are categorized by their message, as sqlx doesn't
+/// expose their number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum ErrorKind {
+    UniqueViolation,
+    ForeignKeyViolation,
+    NotNullViolation,
+    CheckViolation,
+    Syntax,
+    UndefinedTable,
+    UndefinedColumn,
+    /// A deadlock or a serialization failure, the transaction can be retried.
+    Deadlock,
+    /// The database or a row is locked by another connection.
+    Busy,
+    /// Any other error reported by the database.
+    Database,
+    /// The connection failed or was lost, or no connection of the pool was available.
+    Connection,
+    InvalidDbUrl,
+    DatabaseNotLoaded,
+    TransactionNotFound,
+    CursorNotFound,
+    Migration,
+    MigrationsNotSupported,
+    InvalidBindValue,
+    MissingParameter,
+    UnusedParameters,
+    DuplicateColumn,
+    UnsupportedDatatype,
+    Decode,
+    Encode,
+    Other,
+}
+
+/// The details of an error, as far as the database reports them.
+#[derive(Default, Serialize)]
+struct Details {
+    /// The error code of the database: the extended result code of SQLite, the error
+    /// number of MySQL or the SQLSTATE of Postgres.
+    code: Option<String>,
+    sqlstate: Option<String>,
+    constraint: Option<String>,
+    table: Option<String>,
+    column: Option<String>,
+}
+
+impl Details {
+    fn column(column: &str) -> Self {
+        Self {
+            column: Some(column.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The payload of an error sent to the frontend.
+#[derive(Serialize)]
+struct Payload {
+    kind: ErrorKind,
+    message: String,
+    #[serde(flatten)]
+    details: Details,
+}
+
+impl Error {
+    /// The category of the error. A failed statement of a batch has the kind of its error.
+    pub fn kind(&self) -> ErrorKind {
+        self.describe().0
+    }
+
+    fn describe(&self) -> (ErrorKind, Details) {
+        let kind = match self {
+            Error::Sql(e) => return sql(e),
+            Error::BatchStatement { source, .. } => return source.describe(),
+            Error::Decode { column, .. } => return (ErrorKind::Decode, Details::column(column)),
+            Error::DuplicateColumn(column) => {
+                return (ErrorKind::DuplicateColumn, Details::column(column))
+            }
+            Error::Migration(_) => ErrorKind::Migration,
+            Error::Tauri(_) => ErrorKind::Other,
+            Error::InvalidDbUrl(_) => ErrorKind::InvalidDbUrl,
+            Error::DatabaseNotLoaded(_) => ErrorKind::DatabaseNotLoaded,
+            Error::TransactionNotFound(_) => ErrorKind::TransactionNotFound,
+            Error::CursorNotFound(_) => ErrorKind::CursorNotFound,
+            Error::MigrationsNotSupported(_) => ErrorKind::MigrationsNotSupported,
+            Error::InvalidBindValue(_) => ErrorKind::InvalidBindValue,
+            Error::MissingParameter(_) => ErrorKind::MissingParameter,
+            Error::UnusedParameters(_) => ErrorKind::UnusedParameters,
+            Error::Encode(_) => ErrorKind::Encode,
+            Error::UnsupportedDatatype(_) => ErrorKind::UnsupportedDatatype,
+        };
+        (kind, Details::default())
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (kind, details) = self.describe();
+        Payload {
+            kind,
+            message: self.to_string(),
+            details,
+        }
+        .serialize(serializer)
+    }
+}
+
+fn sql(error: &sqlx::Error) -> (ErrorKind, Details) {
+    let kind = match error {
+        sqlx::Error::Database(e) => return database(e.as_ref()),
+        sqlx::Error::Configuration(_) => ErrorKind::InvalidDbUrl,
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::Protocol(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => ErrorKind::Connection,
+        sqlx::Error::ColumnDecode { .. } | sqlx::Error::Decode(_) => ErrorKind::Decode,
+        sqlx::Error::Migrate(_) => ErrorKind::Migration,
+        _ => ErrorKind::Other,
+    };
+    (kind, Details::default())
+}
+
+fn database(error: &(dyn DatabaseError + 'static)) -> (ErrorKind, Details) {
+    #[cfg(feature = "sqlite")]
+    if let Some(e) = error.try_downcast_ref::<SqliteError>() {
+        return sqlite(e.code().as_deref().unwrap_or_default(), e.message());
+    }
+    #[cfg(feature = "mysql")]
+    if let Some(e) = error.try_downcast_ref::<MySqlDatabaseError>() {
+        return mysql(e.number(), e.code(), e.message());
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(e) = error.try_downcast_ref::<PgDatabaseError>() {
+        return postgres(e);
+    }
+    #[cfg(feature = "mssql")]
+    if error.try_downcast_ref::<MssqlDatabaseError>().is_some() {
+        return mssql(error.message());
+    }
+
+    let details = Details {
+        code: error.code().map(String::from),
+        ..Default::default()
+    };
+    (ErrorKind::Database, details)
+}
+
+/// Reads the kind from the extended result code, and the details from messages such as
+/// `UNIQUE constraint failed: users.email` or `no such table: users`.
+#[cfg(feature = "sqlite")]
+fn sqlite(code: &str, message: &str) -> (ErrorKind, Details) {
+    let failed = message
+        .split_once("constraint failed: ")
+        .map(|(_, target)| target);
+    // `table.column`, several columns are separated by commas
+    let target = failed
+        .filter(|target| !target.contains(','))
+        .and_then(|target| target.split_once('.'));
+
+    let mut details = Details::default();
+    let kind = match code.parse::<i32>().unwrap_or_default() {
+        // SQLITE_CONSTRAINT_UNIQUE, SQLITE_CONSTRAINT_PRIMARYKEY
+        2067 | 1555 => ErrorKind::UniqueViolation,
+        // SQLITE_CONSTRAINT_FOREIGNKEY
+        787 => ErrorKind::ForeignKeyViolation,
+        // SQLITE_CONSTRAINT_NOTNULL
+        1299 => ErrorKind::NotNullViolation,
+        // SQLITE_CONSTRAINT_CHECK, reporting the name or the expression of the constraint
+        275 => {
+            details.constraint = failed.map(String::from);
+            ErrorKind::CheckViolation
+        }
+        // SQLITE_BUSY, SQLITE_LOCKED and their extended codes
+        code if matches!(code & 0xFF, 5 | 6) => ErrorKind::Busy,
+        _ => match message.split_once(": ") {
+            Some(("no such table", table)) => {
+                details.table = Some(table.to_string());
+                ErrorKind::UndefinedTable
+            }
+            Some(("no such column", column)) => {
+                details.column = Some(column.to_string());
+                ErrorKind::UndefinedColumn
+            }
+            _ if message.ends_with("syntax error") || message == "incomplete input" => {
+                ErrorKind::Syntax
+            }
+            _ => ErrorKind::Database,
+        },
+    };
+    if let (ErrorKind::UniqueViolation | ErrorKind::NotNullViolation, Some((table, column))) =
+        (kind, target)
+    {
+        details.table = Some(table.to_string());
+        details.column = Some(column.to_string());
+    }
+
+    details.code = Some(code.to_string());
+    (kind, details)
+}
+
+/// Reads the kind from the error number, and the details from messages such as
+/// ``Duplicate entry 'a' for key 'users.email'`` or ``Column 'name' cannot be null``.
+#[cfg(feature = "mysql")]
+fn mysql(number: u16, sqlstate: Option<&str>, message: &str) -> (ErrorKind, Details) {
+    // `db.table`, or `table` in older versions
+    let qualified = |name: &str| match name.rsplit_once('.') {
+        Some((table, name)) => (Some(table.to_string()), name.to_string()),
+        None => (None, name.to_string()),
+    };
+
+    let mut details = Details::default();
+    let kind = match number {
+        1062 | 1586 => {
+            if let Some(key) = quoted(message, "for key '", '\'') {
+                let (table, constraint) = qualified(key);
+                details.table = table;
+                details.constraint = Some(constraint);
+            }
+            ErrorKind::UniqueViolation
+        }
+        // `(`db`.`child`, CONSTRAINT `fk` FOREIGN KEY (`column`) REFERENCES ...)`
+        1216 | 1217 | 1451 | 1452 => {
+            details.table = quoted(message, "`.`", '`').map(String::from);
+            details.constraint = quoted(message, "CONSTRAINT `", '`').map(String::from);
+            details.column = quoted(message, "FOREIGN KEY (`", '`').map(String::from);
+            ErrorKind::ForeignKeyViolation
+        }
+        1048 | 1364 => {
+            details.column = quoted(message, "olumn '", '\'').map(String::from);
+            ErrorKind::NotNullViolation
+        }
+        3819 => {
+            details.constraint = quoted(message, "constraint '", '\'').map(String::from);
+            ErrorKind::CheckViolation
+        }
+        1064 => ErrorKind::Syntax,
+        1146 => {
+            details.table = quoted(message, "Table '", '\'').map(|name| qualified(name).1);
+            ErrorKind::UndefinedTable
+        }
+        1054 => {
+            details.column = quoted(message, "column '", '\'').map(|name| qualified(name).1);
+            ErrorKind::UndefinedColumn
+        }
+        1213 => ErrorKind::Deadlock,
+        1205 => ErrorKind::Busy,
+        _ => ErrorKind::Database,
+    };
+
+    details.code = Some(number.to_string());
+    details.sqlstate = sqlstate.map(String::from);
+    (kind, details)
+}
+
+#[cfg(feature = "postgres")]
+fn postgres(error: &PgDatabaseError) -> (ErrorKind, Details) {
+    let kind = match error.code() {
+        "23505" => ErrorKind::UniqueViolation,
+        "23503" => ErrorKind::ForeignKeyViolation,
+        "23502" => ErrorKind::NotNullViolation,
+        "23514" => ErrorKind::CheckViolation,
+        "42601" => ErrorKind::Syntax,
+        "42P01" => ErrorKind::UndefinedTable,
+        "42703" => ErrorKind::UndefinedColumn,
+        // serialization_failure, deadlock_detected
+        "40001" | "40P01" => ErrorKind::Deadlock,
+        // lock_not_available
+        "55P03" => ErrorKind::Busy,
+        code if code.starts_with("08") => ErrorKind::Connection,
+        _ => ErrorKind::Database,
+    };
+
+    let details = Details {
+        code: Some(error.code().to_string()),
+        sqlstate: Some(error.code().to_string()),
+        constraint: error.constraint().map(String::from),
+        table: error.table().map(String::from),
+        column: error.column().map(String::from),
+    };
+    (kind, details)
+}
+
+/// Reads the kind and details from messages such as ``Violation of UNIQUE KEY constraint
+/// 'UQ_users_email'. Cannot insert duplicate key in object 'dbo.users'.``
+#[cfg(feature = "mssql")]
+fn mssql(message: &str) -> (ErrorKind, Details) {
+    let object = |name: &str| name.rsplit('.').next().unwrap_or(name).to_string();
+
+    let mut details = Details::default();
+    let kind = if message.starts_with("Violation of PRIMARY KEY constraint")
+        || message.starts_with("Violation of UNIQUE KEY constraint")
+    {
+        details.constraint = quoted(message, "constraint '", '\'').map(String::from);
+        details.table = quoted(message, "object '", '\'').map(object);
+        ErrorKind::UniqueViolation
+    } else if message.contains("conflicted with the FOREIGN KEY constraint")
+        || message.contains("conflicted with the REFERENCE constraint")
+        || message.contains("conflicted with the CHECK constraint")
+    {
+        // `... constraint "FK_name". The conflict occurred in database "db", table "dbo.t", column 'id'.`
+        details.constraint = quoted(message, "constraint \"", '"').map(String::from);
+        details.table = quoted(message, "table \"", '"').map(object);
+        details.column = quoted(message, "column '", '\'').map(String::from);
+        match message.contains("CHECK constraint") {
+            true => ErrorKind::CheckViolation,
+            false => ErrorKind::ForeignKeyViolation,
+        }
+    } else if message.starts_with("Cannot insert the value NULL into column") {
+        details.column = quoted(message, "column '", '\'').map(String::from);
+        details.table = quoted(message, "table '", '\'').map(object);
+        ErrorKind::NotNullViolation
+    } else if message.starts_with("Incorrect syntax near") {
+        ErrorKind::Syntax
+    } else if message.starts_with("Invalid object name") {
+        details.table = quoted(message, "name '", '\'').map(object);
+        ErrorKind::UndefinedTable
+    } else if message.starts_with("Invalid column name") {
+        details.column = quoted(message, "name '", '\'').map(String::from);
+        ErrorKind::UndefinedColumn
+    } else if message.contains("deadlocked") {
+        ErrorKind::Deadlock
+    } else if message.starts_with("Lock request time out period exceeded") {
+        ErrorKind::Busy
+    } else {
+        ErrorKind::Database
+    };
+    (kind, details)
+}
+
+/// The text between `prefix` and the next `quote` in `message`.
+#[cfg(any(feature = "mysql", feature = "mssql"))]
+fn quoted<'a>(message: &'a str, prefix: &str, quote: char) -> Option<&'a str> {
+    let (_, rest) = message.split_once(prefix)?;
+    rest.split_once(quote).map(|(text, _)| text)
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(all(test, any(feature = "sqlite", feature = "mysql", feature = "mssql")))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_errors() {
+        let (kind, details) = sqlite("2067", "UNIQUE constraint failed: users.email");
+        assert_eq!(kind, ErrorKind::UniqueViolation);
+        assert_eq!(details.table.as_deref(), Some("users"));
+        assert_eq!(details.column.as_deref(), Some("email"));
+        assert_eq!(details.code.as_deref(), Some("2067"));
+
+        // several columns can't be reported as one
+        let (kind, details) = sqlite("1555", "UNIQUE constraint failed: t.a, t.b");
+        assert_eq!(kind, ErrorKind::UniqueViolation);
+        assert_eq!(details.table, None);
+        assert_eq!(details.column, None);
+
+        let (kind, details) = sqlite("1299", "NOT NULL constraint failed: users.name");
+        assert_eq!(kind, ErrorKind::NotNullViolation);
+        assert_eq!(details.column.as_deref(), Some("name"));
+
+        let (kind, details) = sqlite("787", "FOREIGN KEY constraint failed");
+        assert_eq!(kind, ErrorKind::ForeignKeyViolation);
+        assert_eq!(details.table, None);
+
+        let (kind, details) = sqlite("275", "CHECK constraint failed: age_positive");
+        assert_eq!(kind, ErrorKind::CheckViolation);
+        assert_eq!(details.constraint.as_deref(), Some("age_positive"));
+
+        // SQLITE_BUSY, SQLITE_BUSY_SNAPSHOT and SQLITE_LOCKED
+        for code in ["5", "517", "6"] {
+            assert_eq!(sqlite(code, "database is locked").0, ErrorKind::Busy);
+        }
+
+        let (kind, details) = sqlite("1", "no such table: missing");
+        assert_eq!(kind, ErrorKind::UndefinedTable);
+        assert_eq!(details.table.as_deref(), Some("missing"));
+
+        let (kind, details) = sqlite("1", "no such column: missing");
+        assert_eq!(kind, ErrorKind::UndefinedColumn);
+        assert_eq!(details.column.as_deref(), Some("missing"));
+
+        assert_eq!(
+            sqlite("1", "near \"SELEC\": syntax error").0,
+            ErrorKind::Syntax
+        );
+        assert_eq!(sqlite("1", "incomplete input").0, ErrorKind::Syntax);
+        assert_eq!(sqlite("1", "something else").0, ErrorKind::Database);
+        assert_eq!(sqlite("", "").0, ErrorKind::Database);
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn mysql_errors() {
+        let (kind, details) = mysql(
+            1062,
+            Some("23000"),
+            "Duplicate entry 'a@b.c' for key 'users.email'",
+        );
+        assert_eq!(kind, ErrorKind::UniqueViolation);
+        assert_eq!(details.table.as_deref(), Some("users"));
+        assert_eq!(details.constraint.as_deref(), Some("email"));
+        assert_eq!(details.code.as_deref(), Some("1062"));
+        assert_eq!(details.sqlstate.as_deref(), Some("23000"));
+
+        // before MySQL 8, the key isn't qualified
+        let (_, details) = mysql(1062, None, "Duplicate entry 'a' for key 'email'");
+        assert_eq!(details.table, None);
+        assert_eq!(details.constraint.as_deref(), Some("email"));
+        assert_eq!(details.sqlstate, None);
+
+        let (kind, details) = mysql(
+            1452,
+            Some("23000"),
+            "Cannot add or update a child row: a foreign key constraint fails \
+             (`db`.`posts`, CONSTRAINT `posts_user` FOREIGN KEY (`user_id`) \
+             REFERENCES `users` (`id`))",
+        );
+        assert_eq!(kind, ErrorKind::ForeignKeyViolation);
+        assert_eq!(details.table.as_deref(), Some("posts"));
+        assert_eq!(details.constraint.as_deref(), Some("posts_user"));
+        assert_eq!(details.column.as_deref(), Some("user_id"));
+
+        let (kind, details) = mysql(1048, None, "Column 'name' cannot be null");
+        assert_eq!(kind, ErrorKind::NotNullViolation);
+        assert_eq!(details.column.as_deref(), Some("name"));
+
+        let (kind, details) = mysql(1364, None, "Field 'name' doesn't have a default value");
+        assert_eq!(kind, ErrorKind::NotNullViolation);
+        assert_eq!(details.column, None);
+
+        let (kind, details) = mysql(3819, None, "Check constraint 'age_positive' is violated.");
+        assert_eq!(kind, ErrorKind::CheckViolation);
+        assert_eq!(details.constraint.as_deref(), Some("age_positive"));
+
+        let (kind, details) = mysql(1146, None, "Table 'db.missing' doesn't exist");
+        assert_eq!(kind, ErrorKind::UndefinedTable);
+        assert_eq!(details.table.as_deref(), Some("missing"));
+
+        let (kind, details) = mysql(1054, None, "Unknown column 'u.missing' in 'field list'");
+        assert_eq!(kind, ErrorKind::UndefinedColumn);
+        assert_eq!(details.column.as_deref(), Some("missing"));
+
+        assert_eq!(mysql(1064, None, "").0, ErrorKind::Syntax);
+        assert_eq!(mysql(1213, None, "").0, ErrorKind::Deadlock);
+        assert_eq!(mysql(1205, None, "").0, ErrorKind::Busy);
+        assert_eq!(mysql(1045, None, "").0, ErrorKind::Database);
+    }
+
+    #[cfg(feature = "mssql")]
+    #[test]
+    fn mssql_errors() {
+        let (kind, details) = mssql(
+            "Violation of UNIQUE KEY constraint 'UQ_users_email'. Cannot insert duplicate \
+             key in object 'dbo.users'. The duplicate key value is (a@b.c).",
+        );
+        assert_eq!(kind, ErrorKind::UniqueViolation);
+        assert_eq!(details.constraint.as_deref(), Some("UQ_users_email"));
+        assert_eq!(details.table.as_deref(), Some("users"));
+
+        let (kind, details) = mssql(
+            "The INSERT statement conflicted with the FOREIGN KEY constraint \"FK_posts_users\". \
+             The conflict occurred in database \"db\", table \"dbo.users\", column 'id'.",
+        );
+        assert_eq!(kind, ErrorKind::ForeignKeyViolation);
+        assert_eq!(details.constraint.as_deref(), Some("FK_posts_users"));
+        assert_eq!(details.table.as_deref(), Some("users"));
+        assert_eq!(details.column.as_deref(), Some("id"));
+
+        let (kind, details) = mssql(
+            "The INSERT statement conflicted with the CHECK constraint \"CK_age\". \
+             The conflict occurred in database \"db\", table \"dbo.users\", column 'age'.",
+        );
+        assert_eq!(kind, ErrorKind::CheckViolation);
+        assert_eq!(details.constraint.as_deref(), Some("CK_age"));
+
+        let (kind, details) = mssql(
+            "Cannot insert the value NULL into column 'name', table 'db.dbo.users'; \
+             column does not allow nulls. INSERT fails.",
+        );
+        assert_eq!(kind, ErrorKind::NotNullViolation);
+        assert_eq!(details.column.as_deref(), Some("name"));
+        assert_eq!(details.table.as_deref(), Some("users"));
+
+        let (kind, details) = mssql("Invalid object name 'dbo.missing'.");
+        assert_eq!(kind, ErrorKind::UndefinedTable);
+        assert_eq!(details.table.as_deref(), Some("missing"));
+
+        let (kind, details) = mssql("Invalid column name 'missing'.");
+        assert_eq!(kind, ErrorKind::UndefinedColumn);
+        assert_eq!(details.column.as_deref(), Some("missing"));
+
+        assert_eq!(mssql("Incorrect syntax near 'SELEC'.").0, ErrorKind::Syntax);
+        assert_eq!(
+            mssql(
+                "Transaction (Process ID 52) was deadlocked on lock resources with another \
+                 process and has been chosen as the deadlock victim. Rerun the transaction."
+            )
+            .0,
+            ErrorKind::Deadlock
+        );
+        assert_eq!(
+            mssql("Lock request time out period exceeded.").0,
+            ErrorKind::Busy
+        );
+        assert_eq!(mssql("Login failed.").0, ErrorKind::Database);
+    }
+}
@@ -0,0 +1,670 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Conversions between GeoJSON and the binary geometry formats of the drivers: the EWKB
+//! of PostGIS, the SRID-prefixed WKB of MySQL and the blobs of SpatiaLite.
+
+// the formats of the drivers that aren't enabled are unused
+#![cfg_attr(
+    not(all(feature = "sqlite", feature = "mysql", feature = "postgres")),
+    allow(dead_code)
+)]
+
+use serde_json::{json, Map, Value as JsonValue};
+
+// type codes of WKB, shared by the classes of SpatiaLite
+const POINT: u32 = 1;
+const LINE_STRING: u32 = 2;
+const POLYGON: u32 = 3;
+const MULTI_POINT: u32 = 4;
+const MULTI_LINE_STRING: u32 = 5;
+const MULTI_POLYGON: u32 = 6;
+const GEOMETRY_COLLECTION: u32 = 7;
+
+// flags of the EWKB type codes
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+// markers of SpatiaLite blobs
+const SPATIALITE_START: u8 = 0x00;
+const SPATIALITE_MBR_END: u8 = 0x7C;
+const SPATIALITE_ENTITY: u8 = 0x69;
+const SPATIALITE_END: u8 = 0xFE;
+
+/// The coordinates of a point in the GeoJSON order: x, y, then z and m when present.
+/// Empty for an empty point.
+type Position = Vec<f64>;
+
+/// A geometry of the simple features model, with the SRID of its coordinate system.
+#[derive(Debug)]
+pub(crate) struct Geometry {
+    srid: u32,
+    shape: Shape,
+}
+
+#[derive(Debug)]
+enum Shape {
+    Point(Position),
+    LineString(Vec<Position>),
+    Polygon(Vec<Vec<Position>>),
+    /// A multi-geometry or a geometry collection, by type code.
+    Collection(u32, Vec<Shape>),
+}
+
+/// A binary geometry format.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// WKB with the extended type codes and SRID of PostGIS.
+    Ewkb,
+    /// WKB with the type codes of ISO 19125.
+    Wkb,
+    /// The body of a SpatiaLite blob, whose nested geometries start with a marker
+    /// instead of their byte order.
+    SpatiaLite,
+}
+
+impl Geometry {
+    /// Reads a GeoJSON geometry, or the geometry of a GeoJSON feature.
+    ///
+    /// The SRID is read from a `crs` member naming an EPSG code, as written by
+    /// [`Geometry::to_geojson`], and is 0 without one.
+    pub(crate) fn from_geojson(value: &JsonValue) -> Result<Self, String> {
+        let value = match value.get("type") {
+            Some(ty) if ty == "Feature" => value.get("geometry").unwrap_or(&JsonValue::Null),
+            _ => value,
+        };
+        let object = value
+            .as_object()
+            .ok_or_else(|| format!("expected a GeoJSON geometry, got {value}"))?;
+        let shape = shape(object)?;
+
+        let dims = shape.dims();
+        let mut mixed = false;
+        shape.visit(&mut |position| mixed |= !position.is_empty() && position.len() != dims);
+        if mixed {
+            return Err("positions must all have the same number of coordinates".into());
+        }
+
+        Ok(Self {
+            srid: srid(object.get("crs"))?,
+            shape,
+        })
+    }
+
+    /// Writes the geometry as GeoJSON, with a `crs` member naming its SRID when it has one.
+    pub(crate) fn to_geojson(&self) -> JsonValue {
+        let mut geometry = self.shape.to_geojson();
+        if self.srid != 0 {
+            geometry["crs"] = json!({
+                "type": "name",
+                "properties": { "name": format!("EPSG:{}", self.srid) },
+            });
+        }
+        geometry
+    }
+
+    /// Reads WKB, or the EWKB of PostGIS with its SRID.
+    pub(crate) fn from_ewkb(bytes: &[u8]) -> Result<Self, String> {
+        let mut srid = 0;
+        let shape = read_shape(&mut Reader::new(bytes), Format::Ewkb, &mut srid)?;
+        Ok(Self { srid, shape })
+    }
+
+    /// Writes the EWKB of PostGIS, with the SRID when it isn't 0.
+    pub(crate) fn to_ewkb(&self) -> Vec<u8> {
+        let mut writer = Writer::new(self.shape.dims());
+        let srid = (self.srid != 0).then_some(self.srid);
+        writer.shape(&self.shape, Format::Ewkb, srid);
+        writer.bytes
+    }
+
+    /// Reads the internal format of MySQL: the SRID followed by WKB.
+    pub(crate) fn from_mysql(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes);
+        reader.little_endian = true;
+        let srid = reader.u32()?;
+        let shape = read_shape(&mut reader, Format::Wkb, &mut 0)?;
+        Ok(Self { srid, shape })
+    }
+
+    /// Writes the internal format of MySQL.
+    pub(crate) fn to_mysql(&self) -> Vec<u8> {
+        let mut writer = Writer::new(self.shape.dims());
+        writer.u32(self.srid);
+        writer.shape(&self.shape, Format::Wkb, None);
+        writer.bytes
+    }
+
+    /// Whether `bytes` has the markers of a SpatiaLite blob.
+    pub(crate) fn is_spatialite(bytes: &[u8]) -> bool {
+        // start, byte order, SRID, MBR, MBR end and class
+        bytes.len() > 43
+            && bytes[0] == SPATIALITE_START
+            && bytes[1] <= 1
+            && bytes[38] == SPATIALITE_MBR_END
+            && bytes.last() == Some(&SPATIALITE_END)
+    }
+
+    /// Reads a SpatiaLite blob. Compressed geometries aren't supported.
+    pub(crate) fn from_spatialite(bytes: &[u8]) -> Result<Self, String> {
+        if !Self::is_spatialite(bytes) {
+            return Err("not a SpatiaLite geometry".into());
+        }
+        let mut reader = Reader::new(&bytes[1..bytes.len() - 1]);
+        reader.byte_order()?;
+        let srid = reader.u32()?;
+        // the MBR is computed again when writing
+        reader.take::<33>()?;
+        let shape = read_shape(&mut reader, Format::SpatiaLite, &mut 0)?;
+        Ok(Self { srid, shape })
+    }
+
+    /// Writes a SpatiaLite blob.
+    pub(crate) fn to_spatialite(&self) -> Vec<u8> {
+        let mut mbr = [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        self.shape.visit(&mut |position| {
+            if let [x, y, ..] = position[..] {
+                mbr = [mbr[0].min(x), mbr[1].min(y), mbr[2].max(x), mbr[3].max(y)];
+            }
+        });
+        if mbr[0] > mbr[2] {
+            mbr = [0.0; 4];
+        }
+
+        let mut writer = Writer::new(self.shape.dims());
+        writer.bytes.extend([SPATIALITE_START, 1]);
+        writer.u32(self.srid);
+        mbr.into_iter().for_each(|n| writer.f64(n));
+        writer.bytes.push(SPATIALITE_MBR_END);
+        writer.shape(&self.shape, Format::SpatiaLite, None);
+        writer.bytes.push(SPATIALITE_END);
+        writer.bytes
+    }
+}
+
+impl Shape {
+    fn code(&self) -> u32 {
+        match self {
+            Shape::Point(_) => POINT,
+            Shape::LineString(_) => LINE_STRING,
+            Shape::Polygon(_) => POLYGON,
+            Shape::Collection(code, _) => *code,
+        }
+    }
+
+    /// The number of coordinates of the positions, from the first non-empty one.
+    fn dims(&self) -> usize {
+        let mut dims = None;
+        self.visit(&mut |position| {
+            if dims.is_none() && !position.is_empty() {
+                dims = Some(position.len());
+            }
+        });
+        dims.unwrap_or(2)
+    }
+
+    /// Calls `f` with every position of the geometry.
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Position)) {
+        match self {
+            Shape::Point(position) => f(position),
+            Shape::LineString(positions) => positions.iter().for_each(f),
+            Shape::Polygon(rings) => rings.iter().flatten().for_each(f),
+            Shape::Collection(_, shapes) => shapes.iter().for_each(|shape| shape.visit(f)),
+        }
+    }
+
+    fn to_geojson(&self) -> JsonValue {
+        let ty = match self.code() {
+            POINT => "Point",
+            LINE_STRING => "LineString",
+            POLYGON => "Polygon",
+            MULTI_POINT => "MultiPoint",
+            MULTI_LINE_STRING => "MultiLineString",
+            MULTI_POLYGON => "MultiPolygon",
+            _ => "GeometryCollection",
+        };
+        match self {
+            Shape::Collection(GEOMETRY_COLLECTION, shapes) => json!({
+                "type": ty,
+                "geometries": shapes.iter().map(Shape::to_geojson).collect::<Vec<_>>(),
+            }),
+            shape => json!({ "type": ty, "coordinates": shape.coordinates() }),
+        }
+    }
+
+    fn coordinates(&self) -> JsonValue {
+        match self {
+            Shape::Point(position) => json!(position),
+            Shape::LineString(positions) => json!(positions),
+            Shape::Polygon(rings) => json!(rings),
+            Shape::Collection(_, shapes) => shapes.iter().map(Shape::coordinates).collect(),
+        }
+    }
+}
+
+/// Reads the geometry of a GeoJSON geometry object.
+fn shape(object: &Map<String, JsonValue>) -> Result<Shape, String> {
+    let ty = object
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default();
+    if ty == "GeometryCollection" {
+        let geometries = object
+            .get("geometries")
+            .ok_or("a GeometryCollection needs `geometries`")?;
+        let shapes = list(geometries, |geometry| match geometry.as_object() {
+            Some(object) => shape(object),
+            None => Err(format!("expected a GeoJSON geometry, got {geometry}")),
+        })?;
+        return Ok(Shape::Collection(GEOMETRY_COLLECTION, shapes));
+    }
+
+    let coordinates = || {
+        object
+            .get("coordinates")
+            .ok_or_else(|| format!("a {ty} needs `coordinates`"))
+    };
+    Ok(match ty {
+        "Point" => match coordinates()? {
+            JsonValue::Array(items) if items.is_empty() => Shape::Point(Vec::new()),
+            coordinates => Shape::Point(position(coordinates)?),
+        },
+        "LineString" => Shape::LineString(list(coordinates()?, position)?),
+        "Polygon" => Shape::Polygon(list(coordinates()?, |ring| list(ring, position))?),
+        "MultiPoint" => Shape::Collection(
+            MULTI_POINT,
+            list(coordinates()?, |point| position(point).map(Shape::Point))?,
+        ),
+        "MultiLineString" => Shape::Collection(
+            MULTI_LINE_STRING,
+            list(coordinates()?, |line| {
+                list(line, position).map(Shape::LineString)
+            })?,
+        ),
+        "MultiPolygon" => Shape::Collection(
+            MULTI_POLYGON,
+            list(coordinates()?, |polygon| {
+                list(polygon, |ring| list(ring, position)).map(Shape::Polygon)
+            })?,
+        ),
+        _ => return Err(format!("unknown GeoJSON geometry type {ty:?}")),
+    })
+}
+
+fn list<T>(
+    value: &JsonValue,
+    item: impl FnMut(&JsonValue) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("expected an array, got {value}"))?
+        .iter()
+        .map(item)
+        .collect()
+}
+
+fn position(value: &JsonValue) -> Result<Position, String> {
+    let position = list(value, |n| {
+        n.as_f64()
+            .ok_or_else(|| format!("expected a coordinate, got {n}"))
+    })?;
+    match position.len() {
+        2..=4 => Ok(position),
+        _ => Err(format!("positions have 2 to 4 coordinates, got {value}")),
+    }
+}
+
+/// Reads the SRID of a `crs` member such as `{ "type": "name", "properties": { "name": "EPSG:4326" } }`.
+fn srid(crs: Option<&JsonValue>) -> Result<u32, String> {
+    let Some(crs) = crs else {
+        return Ok(0);
+    };
+    let name = crs
+        .pointer("/properties/name")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| format!("unsupported crs {crs}, expected a named crs"))?;
+    if name.ends_with("CRS84") {
+        return Ok(4326);
+    }
+    // `EPSG:4326` or `urn:ogc:def:crs:EPSG::4326`
+    name.rsplit(':')
+        .next()
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("unsupported crs {name}, expected an EPSG code"))
+}
+
+/// Reads a geometry and the geometries nested in it, updating `srid` with the SRID of EWKB.
+fn read_shape(reader: &mut Reader<'_>, format: Format, srid: &mut u32) -> Result<Shape, String> {
+    if format != Format::SpatiaLite {
+        reader.byte_order()?;
+    }
+    let code = reader.u32()?;
+    if format == Format::Ewkb && code & EWKB_SRID != 0 {
+        *srid = reader.u32()?;
+    }
+
+    let iso = code & 0x0FFF_FFFF;
+    let z = code & EWKB_Z != 0 || matches!(iso / 1000, 1 | 3);
+    let m = code & EWKB_M != 0 || matches!(iso / 1000, 2 | 3);
+    if iso / 1000 > 3 {
+        return Err(format!("unsupported geometry type {iso}"));
+    }
+
+    Ok(match iso % 1000 {
+        POINT => {
+            let position = reader.position(z, m)?;
+            // empty points are written with NaN coordinates
+            match position.iter().all(|n| n.is_nan()) {
+                true => Shape::Point(Vec::new()),
+                false => Shape::Point(position),
+            }
+        }
+        LINE_STRING => Shape::LineString(reader.positions(z, m)?),
+        POLYGON => Shape::Polygon(
+            (0..reader.count()?)
+                .map(|_| reader.positions(z, m))
+                .collect::<Result<_, _>>()?,
+        ),
+        code @ MULTI_POINT..=GEOMETRY_COLLECTION => Shape::Collection(
+            code,
+            (0..reader.count()?)
+                .map(|_| {
+                    if format == Format::SpatiaLite && reader.u8()? != SPATIALITE_ENTITY {
+                        return Err("invalid SpatiaLite geometry".to_string());
+                    }
+                    read_shape(reader, format, srid)
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => return Err(format!("unsupported geometry type {iso}")),
+    })
+}
+
+/// A cursor over a binary geometry.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            little_endian: false,
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (head, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or("truncated geometry")?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    fn byte_order(&mut self) -> Result<(), String> {
+        self.little_endian = match self.u8()? {
+            0 => false,
+            1 => true,
+            order => return Err(format!("invalid byte order {order}")),
+        };
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take()?;
+        Ok(match self.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take()?;
+        Ok(match self.little_endian {
+            true => f64::from_le_bytes(bytes),
+            false => f64::from_be_bytes(bytes),
+        })
+    }
+
+    /// Reads a number of items, each taking at least one byte.
+    fn count(&mut self) -> Result<usize, String> {
+        let count = self.u32()? as usize;
+        match count <= self.bytes.len() {
+            true => Ok(count),
+            false => Err("truncated geometry".into()),
+        }
+    }
+
+    fn position(&mut self, z: bool, m: bool) -> Result<Position, String> {
+        let mut position = vec![self.f64()?, self.f64()?];
+        let z = if z { Some(self.f64()?) } else { None };
+        let m = if m { Some(self.f64()?) } else { None };
+        // GeoJSON positions can't have m without z
+        if let Some(z) = z {
+            position.push(z);
+            position.extend(m);
+        }
+        Ok(position)
+    }
+
+    fn positions(&mut self, z: bool, m: bool) -> Result<Vec<Position>, String> {
+        (0..self.count()?).map(|_| self.position(z, m)).collect()
+    }
+}
+
+/// Writes binary geometries, in little endian.
+struct Writer {
+    bytes: Vec<u8>,
+    /// The number of coordinates of every position.
+    dims: usize,
+}
+
+impl Writer {
+    fn new(dims: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            dims,
+        }
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.bytes.extend(n.to_le_bytes());
+    }
+
+    fn f64(&mut self, n: f64) {
+        self.bytes.extend(n.to_le_bytes());
+    }
+
+    fn position(&mut self, position: &Position) {
+        match position.is_empty() {
+            true => (0..self.dims).for_each(|_| self.f64(f64::NAN)),
+            false => position.iter().for_each(|n| self.f64(*n)),
+        }
+    }
+
+    fn positions(&mut self, positions: &[Position]) {
+        self.u32(positions.len() as u32);
+        positions
+            .iter()
+            .for_each(|position| self.position(position));
+    }
+
+    /// Writes `shape` and the geometries nested in it, with `srid` for EWKB.
+    fn shape(&mut self, shape: &Shape, format: Format, srid: Option<u32>) {
+        let mut code = shape.code();
+        match format {
+            Format::Ewkb => {
+                if self.dims >= 3 {
+                    code |= EWKB_Z;
+                }
+                if self.dims == 4 {
+                    code |= EWKB_M;
+                }
+                if srid.is_some() {
+                    code |= EWKB_SRID;
+                }
+            }
+            Format::Wkb | Format::SpatiaLite => {
+                code += match self.dims {
+                    3 => 1000,
+                    4 => 3000,
+                    _ => 0,
+                };
+            }
+        }
+        if format != Format::SpatiaLite {
+            self.bytes.push(1);
+        }
+        self.u32(code);
+        if let Some(srid) = srid {
+            self.u32(srid);
+        }
+
+        match shape {
+            Shape::Point(position) => self.position(position),
+            Shape::LineString(positions) => self.positions(positions),
+            Shape::Polygon(rings) => {
+                self.u32(rings.len() as u32);
+                rings.iter().for_each(|ring| self.positions(ring));
+            }
+            Shape::Collection(_, shapes) => {
+                self.u32(shapes.len() as u32);
+                for shape in shapes {
+                    if format == Format::SpatiaLite {
+                        self.bytes.push(SPATIALITE_ENTITY);
+                    }
+                    self.shape(shape, format, None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crs(srid: u32) -> JsonValue {
+        json!({ "type": "name", "properties": { "name": format!("EPSG:{srid}") } })
+    }
+
+    fn geometries() -> Vec<JsonValue> {
+        vec![
+            json!({ "type": "Point", "coordinates": [1.5, -2.0] }),
+            json!({ "type": "Point", "coordinates": [] }),
+            json!({ "type": "LineString", "coordinates": [[0.0, 0.0, 1.0], [1.0, 1.0, 2.0]] }),
+            json!({
+                "type": "Polygon",
+                "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.5], [0.0, 0.0]]],
+                "crs": crs(4326),
+            }),
+            json!({ "type": "MultiPoint", "coordinates": [[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]] }),
+            json!({
+                "type": "MultiPolygon",
+                "coordinates": [
+                    [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]],
+                    [[[2.0, 2.0], [3.0, 2.0], [3.0, 3.0], [2.0, 2.0]]],
+                ],
+                "crs": crs(3857),
+            }),
+            json!({
+                "type": "GeometryCollection",
+                "geometries": [
+                    { "type": "Point", "coordinates": [1.0, 2.0] },
+                    { "type": "MultiLineString", "coordinates": [[[0.0, 0.0], [1.0, 1.0]]] },
+                ],
+            }),
+        ]
+    }
+
+    #[test]
+    fn ewkb_round_trips() {
+        for geojson in geometries() {
+            let ewkb = Geometry::from_geojson(&geojson).unwrap().to_ewkb();
+            assert_eq!(Geometry::from_ewkb(&ewkb).unwrap().to_geojson(), geojson);
+        }
+    }
+
+    #[test]
+    fn ewkb_is_the_one_of_postgis() {
+        let polygon = &geometries()[3];
+        let ewkb = Geometry::from_geojson(polygon).unwrap().to_ewkb();
+        assert_eq!(
+            hex::encode(ewkb),
+            "0103000020e6100000010000000400000000000000000000000000000000000000000000000000f03f\
+             0000000000000000000000000000f03f000000000000f83f00000000000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn mysql_round_trips() {
+        for geojson in geometries() {
+            let bytes = Geometry::from_geojson(&geojson).unwrap().to_mysql();
+            assert_eq!(Geometry::from_mysql(&bytes).unwrap().to_geojson(), geojson);
+        }
+
+        let polygon = Geometry::from_geojson(&geometries()[3]).unwrap().to_mysql();
+        assert_eq!(polygon[..4], 4326u32.to_le_bytes());
+    }
+
+    #[test]
+    fn spatialite_round_trips() {
+        for geojson in geometries() {
+            let blob = Geometry::from_geojson(&geojson).unwrap().to_spatialite();
+            assert!(Geometry::is_spatialite(&blob));
+            assert_eq!(
+                Geometry::from_spatialite(&blob).unwrap().to_geojson(),
+                geojson
+            );
+        }
+    }
+
+    #[test]
+    fn features_and_crs84_are_read() {
+        let feature = json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [1.0, 2.0],
+                "crs": { "type": "name", "properties": { "name": "urn:ogc:def:crs:OGC:1.3:CRS84" } },
+            },
+            "properties": {},
+        });
+        let geometry = Geometry::from_geojson(&feature).unwrap();
+        assert_eq!(geometry.srid, 4326);
+    }
+
+    #[test]
+    fn invalid_geometries_are_rejected() {
+        for geojson in [
+            json!({ "type": "Circle", "coordinates": [0.0, 0.0] }),
+            json!({ "type": "Point" }),
+            json!({ "type": "Point", "coordinates": [1.0] }),
+            json!({ "type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0, 1.0]] }),
+            json!({
+                "type": "Point",
+                "coordinates": [0.0, 0.0],
+                "crs": { "type": "link", "properties": { "href": "https://example.com/crs" } },
+            }),
+        ] {
+            assert!(Geometry::from_geojson(&geojson).is_err(), "{geojson}");
+        }
+
+        assert!(Geometry::from_ewkb(&[1, 1, 0, 0]).is_err());
+        assert!(!Geometry::is_spatialite(b"not a geometry"));
+        assert!(Geometry::from_spatialite(&[0; 50]).is_err());
+    }
+}
@@ -1,16 +1,45 @@
-#[cfg(any(
-  all(feature = "sqlite", feature = "mysql"),
-  all(feature = "sqlite", feature = "postgres"),
-  all(feature = "sqlite", feature = "mssql"),
-  all(feature = "mysql", feature = "postgres"),
-  all(feature = "mysql", feature = "mssql"),
-  all(feature = "postgres", feature = "mssql")
-))]
-compile_error!(
-  "Only one database driver can be enabled. Set the feature flag for the driver of your choice."
-);
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
 
-// #[cfg(not(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql")))]
-// compile_error!(
-//   "Database driver not defined. Please set the feature flag for the driver of your choice."
-// );
\ No newline at end of file
+//! Interface with SQL databases through [sqlx](https://github.com/launchbadge/sqlx). It supports the `sqlite`, `mysql`, `postgres` and `mssql` drivers, enabled by Cargo features.
+//! Any combination of drivers can be enabled at once, the driver of a connection is picked from the scheme of its url.
+
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod bind;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod codec;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod cursor;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod decode;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod error;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod geometry;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod plugin;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod row;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod sql;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+mod wrapper;
+
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+pub use codec::{CodecError, RawValue, TypeCodec};
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+pub use error::{Error, ErrorKind};
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+pub use plugin::{Builder, Migration, MigrationKind, PluginConfig};
+
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+use tauri::{plugin::TauriPlugin, Runtime};
+
+/// Initializes the plugin with the default [`Builder`] configuration.
+///
+/// Use [`Builder`] directly to register migrations.
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres", feature = "mssql"))]
+pub fn init<R: Runtime>() -> TauriPlugin<R, Option<PluginConfig>> {
+    Builder::default().build()
+}
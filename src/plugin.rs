@@ -1,97 +1,206 @@
 use futures_core::future::BoxFuture;
-use serde::{ser::Serializer, Deserialize, Serialize};
-use serde_json::Value as JsonValue;
+use serde::{Deserialize, Serialize};
 use sqlx::{
     error::BoxDynError,
-    migrate::{
-        MigrateDatabase, Migration as SqlxMigration, MigrationSource, MigrationType, Migrator,
-    },
-    Column, Pool, Row,
+    migrate::{Migration as SqlxMigration, MigrationSource, MigrationType, Migrator},
 };
 use tauri::{
     command,
+    ipc::{Channel, Response},
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    AppHandle, Manager, RunEvent, Runtime, State,
+    AppHandle, Manager, RunEvent, Runtime, State, Webview, WindowEvent,
+};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    bind::BindValues,
+    codec::{TypeCodec, TypeCodecs},
+    cursor::{Cursor, CursorPage, DbCursors},
+    error::{Error, Result},
+    row::{BlobEncoding, DecodeOptions, JsonRow, ResultEncoding, SelectFormat},
+    wrapper::{Batch, BatchResult, DbPool, DbTransaction, LastInsertId},
 };
-use tokio::sync::Mutex;
-
-use std::collections::HashMap;
-
-#[cfg(feature = "sqlite")]
-use std::{fs::create_dir_all, path::PathBuf};
-
-// ==== Database Driver Selection ====
-
-#[cfg(feature = "sqlite")]
-type Db = sqlx::sqlite::Sqlite;
-#[cfg(feature = "mysql")]
-type Db = sqlx::mysql::MySql;
-#[cfg(feature = "postgres")]
-type Db = sqlx::postgres::Postgres;
-#[cfg(feature = "mssql")]
-type Db = sqlx::mssql::Mssql;
-
-#[cfg(feature = "sqlite")]
-type LastInsertId = i64;
-#[cfg(any(feature = "mysql", feature = "mssql"))]
-type LastInsertId = u64;
-#[cfg(feature = "postgres")]
-type LastInsertId = u64; // Always returns 0 in postgres
-
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error(transparent)]
-    Sql(#[from] sqlx::Error),
-    #[error(transparent)]
-    Migration(#[from] sqlx::migrate::MigrateError),
-    #[error("database {0} not loaded")]
-    DatabaseNotLoaded(String),
-    #[error("unsupported datatype: {0}")]
-    UnsupportedDatatype(String),
+
+/// A loaded database.
+#[derive(Clone)]
+struct DbInstance {
+    pool: DbPool,
+    options: DecodeOptions,
 }
 
-impl Serialize for Error {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_str(self.to_string().as_ref())
+#[derive(Default)]
+struct DbInstances(Mutex<HashMap<String, DbInstance>>);
+
+impl DbInstances {
+    /// Returns a handle to the pool of a loaded database without holding the lock
+    /// for the duration of the query.
+    async fn get(&self, db: String) -> Result<DbInstance> {
+        self.0
+            .lock()
+            .await
+            .get(&db)
+            .cloned()
+            .ok_or(Error::DatabaseNotLoaded(db))
     }
 }
 
-type Result<T> = std::result::Result<T, Error>;
+/// A transaction together with the savepoints opened on it by nested `begin` calls.
+struct OpenTransaction {
+    transaction: DbTransaction,
+    /// Ids of the savepoint handles, innermost last.
+    savepoints: Vec<String>,
+}
 
-#[cfg(feature = "sqlite")]
-fn app_path<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
-    app.path().app_config_dir().expect("No App path was found!")
+impl OpenTransaction {
+    fn savepoint_name(depth: usize) -> String {
+        format!("tauri_sql_savepoint_{depth}")
+    }
 }
 
-#[cfg(feature = "sqlite")]
-fn path_mapper(mut app_path: PathBuf, connection_string: &str) -> String {
-    app_path.push(
-        connection_string
-            .split_once(':')
-            .expect("Couldn't parse the connection string for DB!")
-            .1,
-    );
+/// An open transaction or savepoint, keyed by its id in [`DbTransactions`].
+struct TransactionHandle {
+    db: String,
+    /// Label of the window whose webview began the transaction.
+    window: String,
+    /// Shared by the transaction and all of its savepoints, `None` once the transaction ended.
+    transaction: Arc<Mutex<Option<OpenTransaction>>>,
+    /// Whether this handle is a savepoint of an enclosing transaction.
+    nested: bool,
+}
 
-    format!(
-        "sqlite:{}",
-        app_path
-            .to_str()
-            .expect("Problem creating fully qualified path to Database file!")
-    )
+#[derive(Default)]
+struct DbTransactions(Mutex<HashMap<String, TransactionHandle>>);
+
+impl DbTransactions {
+    /// Returns the transaction `id` of `db` without holding the lock for the duration of the query.
+    async fn get(&self, id: String, db: &str) -> Result<Arc<Mutex<Option<OpenTransaction>>>> {
+        match self.0.lock().await.get(&id) {
+            Some(handle) if handle.db == db => Ok(handle.transaction.clone()),
+            _ => Err(Error::TransactionNotFound(id)),
+        }
+    }
+
+    /// Forgets every handle pointing to `transaction`.
+    async fn forget(&self, transaction: &Arc<Mutex<Option<OpenTransaction>>>) {
+        self.0
+            .lock()
+            .await
+            .retain(|_, handle| !Arc::ptr_eq(&handle.transaction, transaction));
+    }
+
+    /// Commits or rolls back the transaction or savepoint `id`.
+    ///
+    /// Ending a savepoint also ends every savepoint nested inside of it,
+    /// ending a transaction ends all of its savepoints.
+    async fn end(&self, id: String, commit: bool) -> Result<()> {
+        let handle = self
+            .0
+            .lock()
+            .await
+            .remove(&id)
+            .ok_or_else(|| Error::TransactionNotFound(id.clone()))?;
+
+        if !handle.nested {
+            self.forget(&handle.transaction).await;
+            let open = handle.transaction.lock().await.take();
+            let open = open.ok_or(Error::TransactionNotFound(id))?;
+            return if commit {
+                open.transaction.commit().await
+            } else {
+                open.transaction.rollback().await
+            };
+        }
+
+        let mut open = handle.transaction.lock().await;
+        let open = open.as_mut().ok_or(Error::TransactionNotFound(id.clone()))?;
+        let depth = open
+            .savepoints
+            .iter()
+            .position(|savepoint| *savepoint == id)
+            .ok_or(Error::TransactionNotFound(id))?;
+
+        let inner = open.savepoints.split_off(depth);
+        {
+            let mut transactions = self.0.lock().await;
+            for id in &inner {
+                transactions.remove(id);
+            }
+        }
+
+        let name = OpenTransaction::savepoint_name(depth);
+        if commit {
+            open.transaction.release_savepoint(&name).await
+        } else {
+            open.transaction.rollback_to_savepoint(&name).await
+        }
+    }
+
+    /// Rolls back every open transaction matching `filter`, including its savepoints.
+    async fn rollback_where(&self, filter: impl Fn(&TransactionHandle) -> bool) {
+        let roots = {
+            let mut transactions = self.0.lock().await;
+            let roots = transactions
+                .values()
+                .filter(|handle| !handle.nested && filter(handle))
+                .map(|handle| (handle.db.clone(), handle.transaction.clone()))
+                .collect::<Vec<_>>();
+            transactions.retain(|_, handle| {
+                !roots
+                    .iter()
+                    .any(|(_, transaction)| Arc::ptr_eq(&handle.transaction, transaction))
+            });
+            roots
+        };
+
+        for (db, transaction) in roots {
+            if let Some(open) = transaction.lock().await.take() {
+                if let Err(e) = open.transaction.rollback().await {
+                    log::error!("failed to roll back transaction on {db}: {e}");
+                }
+            }
+        }
+    }
 }
 
+/// Credits of the running `select_stream` calls, keyed by stream id.
+///
+/// Every chunk sent to the webview takes a permit which `ack_stream` gives back,
+/// `cancel_stream` closes the semaphore.
 #[derive(Default)]
-struct DbInstances(Mutex<HashMap<String, Pool<Db>>>);
+struct DbStreams(Mutex<HashMap<String, Arc<Semaphore>>>);
+
+/// A message sent over the channel of a `select_stream` call.
+#[derive(Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+enum StreamEvent {
+    Rows(Vec<JsonRow>),
+    #[serde(rename_all = "camelCase")]
+    End {
+        row_count: u64,
+        cancelled: bool,
+    },
+    Error(Error),
+}
 
 struct Migrations(Mutex<HashMap<String, MigrationList>>);
 
+/// The databases listed in [`PluginConfig::strict`].
+struct StrictDatabases(HashSet<String>);
+
 #[derive(Default, Clone, Deserialize)]
 pub struct PluginConfig {
     #[serde(default)]
     preload: Vec<String>,
+    /// Databases whose queries fail when a value can't be decoded, instead of
+    /// returning `null` for it.
+    #[serde(default)]
+    strict: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -141,36 +250,41 @@ impl MigrationSource<'static> for MigrationList {
 
 #[command]
 async fn load<R: Runtime>(
-    #[allow(unused_variables)] app: AppHandle<R>,
+    app: AppHandle<R>,
     db_instances: State<'_, DbInstances>,
     migrations: State<'_, Migrations>,
+    strict: State<'_, StrictDatabases>,
+    codecs: State<'_, TypeCodecs>,
     db: String,
+    options: Option<DecodeOptions>,
 ) -> Result<String> {
-    #[cfg(feature = "sqlite")]
-    let fqdb = path_mapper(app_path(&app), &db);
-    #[cfg(not(feature = "sqlite"))]
-    let fqdb = db.clone();
-
-    #[cfg(feature = "sqlite")]
-    create_dir_all(app_path(&app)).expect("Problem creating App directory!");
-
-    if !Db::database_exists(&fqdb).await.unwrap_or(false) {
-        Db::create_database(&fqdb).await?;
-    }
-
-    let pool = Pool::connect(&fqdb).await?;
+    let pool = DbPool::connect(&db, &app).await?;
 
     if let Some(migrations) = migrations.0.lock().await.remove(&db) {
         let migrator = Migrator::new(migrations).await?;
-        migrator.run(&pool).await?;
+        pool.migrate(&migrator).await?;
     }
 
-    db_instances.0.lock().await.insert(db.clone(), pool);
+    let options = DecodeOptions {
+        strict: strict.0.contains(&db),
+        codecs: codecs.inner().clone(),
+        ..options.unwrap_or_default()
+    };
+    db_instances
+        .0
+        .lock()
+        .await
+        .insert(db.clone(), DbInstance { pool, options });
     Ok(db)
 }
 
 #[command]
-async fn close(db_instances: State<'_, DbInstances>, db: Option<String>) -> Result<bool> {
+async fn close(
+    db_instances: State<'_, DbInstances>,
+    db_transactions: State<'_, DbTransactions>,
+    db_cursors: State<'_, DbCursors>,
+    db: Option<String>,
+) -> Result<bool> {
     let mut instances = db_instances.0.lock().await;
 
     let pools = if let Some(db) = db {
@@ -179,11 +293,17 @@ async fn close(db_instances: State<'_, DbInstances>, db: Option<String>) -> Resu
         instances.keys().cloned().collect()
     };
 
+    // Open transactions and cursors hold on to their connection and would block the pool from closing.
+    db_transactions
+        .rollback_where(|handle| pools.contains(&handle.db))
+        .await;
+    db_cursors.remove_for(&pools).await;
+
     for pool in pools {
         let db = instances
             .get_mut(&pool)
             .ok_or(Error::DatabaseNotLoaded(pool))?;
-        db.close().await;
+        db.pool.close().await;
     }
 
     Ok(true)
@@ -192,79 +312,329 @@ async fn close(db_instances: State<'_, DbInstances>, db: Option<String>) -> Resu
 #[command]
 async fn execute(
     db_instances: State<'_, DbInstances>,
+    db_transactions: State<'_, DbTransactions>,
+    codecs: State<'_, TypeCodecs>,
     db: String,
     query: String,
-    values: Vec<JsonValue>,
+    values: BindValues,
+    transaction_id: Option<String>,
 ) -> Result<(u64, LastInsertId)> {
-    let mut instances = db_instances.0.lock().await;
-    let db = instances.get_mut(&db).ok_or(Error::DatabaseNotLoaded(db))?;
-
-    let mut query = sqlx::query(&query);
-    for value in values {
-        if value.is_null() {
-            query = query.bind(None::<JsonValue>);
-        } else if value.is_string() {
-            query = query.bind(value.as_str().unwrap().to_owned());
-        } else {
-            query = query.bind(value);
-        }
+    let values = values.encode(&codecs)?;
+    if let Some(id) = transaction_id {
+        let transaction = db_transactions.get(id.clone(), &db).await?;
+        let mut transaction = transaction.lock().await;
+        let open = transaction.as_mut().ok_or(Error::TransactionNotFound(id))?;
+        return open.transaction.execute(&query, values).await;
     }
 
-    let result = query.execute(&*db).await?;
+    let instance = db_instances.get(db).await?;
+    instance.pool.execute(&query, values).await
+}
 
-    #[cfg(feature = "sqlite")]
-    let r = Ok((result.rows_affected(), result.last_insert_rowid()));
-    #[cfg(feature = "mysql")]
-    let r = Ok((result.rows_affected(), result.last_insert_id()));
-    #[cfg(feature = "postgres")]
-    let r = Ok((result.rows_affected(), 0));
-    #[cfg(feature = "mssql")]
-    let r = Ok((result.rows_affected(), result.last_insert_id()));
+/// Runs `query` and returns its rows in the given `format`.
+///
+/// With the `msgpack` encoding the response is binary instead of JSON. `blobs`
+/// overrides the [`BlobEncoding`] of the connection for this query.
+#[command]
+#[allow(clippy::too_many_arguments)]
+async fn select(
+    db_instances: State<'_, DbInstances>,
+    db_transactions: State<'_, DbTransactions>,
+    codecs: State<'_, TypeCodecs>,
+    db: String,
+    query: String,
+    values: BindValues,
+    transaction_id: Option<String>,
+    format: Option<SelectFormat>,
+    encoding: Option<ResultEncoding>,
+    blobs: Option<BlobEncoding>,
+) -> Result<Response> {
+    let values = values.encode(&codecs)?;
+    let format = format.unwrap_or_default();
+    let instance = db_instances.get(db.clone()).await?;
+    let options = instance.options.with_blobs(blobs);
+    let result = if let Some(id) = transaction_id {
+        let transaction = db_transactions.get(id.clone(), &db).await?;
+        let mut transaction = transaction.lock().await;
+        let open = transaction.as_mut().ok_or(Error::TransactionNotFound(id))?;
+        open.transaction
+            .select(&query, values, &options, format)
+            .await?
+    } else {
+        instance
+            .pool
+            .select(&query, values, &options, format)
+            .await?
+    };
 
-    r
+    let body = result.encode(encoding.unwrap_or_default())?;
+    Ok(Response::new(body))
 }
 
+/// Streams the rows of `query` over `on_event` in chunks of `chunk_size` rows and returns the stream id.
+///
+/// At most `window` chunks are sent before the webview acknowledges one of them
+/// with `ack_stream`, the stream can be stopped early with `cancel_stream`.
 #[command]
-async fn select(
+#[allow(clippy::too_many_arguments)]
+async fn select_stream<R: Runtime>(
+    app: AppHandle<R>,
     db_instances: State<'_, DbInstances>,
+    db_streams: State<'_, DbStreams>,
+    codecs: State<'_, TypeCodecs>,
     db: String,
     query: String,
-    values: Vec<JsonValue>,
-) -> Result<Vec<HashMap<String, JsonValue>>> {
-    let mut instances = db_instances.0.lock().await;
-    let db = instances.get_mut(&db).ok_or(Error::DatabaseNotLoaded(db))?;
-
-    let mut query = sqlx::query(&query);
-    for value in values {
-        if value.is_null() {
-            query = query.bind(None::<JsonValue>);
-        } else if value.is_string() {
-            query = query.bind(value.as_str().unwrap().to_owned());
-        } else {
-            query = query.bind(value);
-        }
+    values: BindValues,
+    on_event: Channel<StreamEvent>,
+    chunk_size: Option<usize>,
+    window: Option<usize>,
+    blobs: Option<BlobEncoding>,
+) -> Result<String> {
+    let values = values.encode(&codecs)?;
+    let instance = db_instances.get(db).await?;
+    let options = instance.options.with_blobs(blobs);
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let permits = Arc::new(Semaphore::new(window.unwrap_or(4).max(1)));
+    db_streams
+        .0
+        .lock()
+        .await
+        .insert(id.clone(), permits.clone());
+
+    let stream_id = id.clone();
+    tauri::async_runtime::spawn(async move {
+        let (sender, receiver) = mpsc::channel(1);
+        let producer = instance.pool.select_chunked(
+            &query,
+            values,
+            &options,
+            chunk_size.unwrap_or(100),
+            sender,
+        );
+        let consumer = async {
+            // dropping the receiver when returning early stops the producer
+            let mut receiver = receiver;
+            while let Some(rows) = receiver.recv().await {
+                match permits.acquire().await {
+                    Ok(permit) => permit.forget(),
+                    // the stream was cancelled
+                    Err(_) => return,
+                }
+                if on_event.send(StreamEvent::Rows(rows)).is_err() {
+                    return;
+                }
+            }
+        };
+        let (result, ()) = tokio::join!(producer, consumer);
+
+        let event = match result {
+            Ok(row_count) => StreamEvent::End {
+                row_count,
+                cancelled: permits.is_closed(),
+            },
+            Err(e) => StreamEvent::Error(e),
+        };
+        let _ = on_event.send(event);
+
+        app.state::<DbStreams>().0.lock().await.remove(&stream_id);
+    });
+
+    Ok(id)
+}
+
+/// Acknowledges a chunk of the stream `stream_id`, allowing the next one to be sent.
+#[command]
+async fn ack_stream(db_streams: State<'_, DbStreams>, stream_id: String) -> Result<()> {
+    if let Some(permits) = db_streams.0.lock().await.get(&stream_id) {
+        permits.add_permits(1);
     }
+    Ok(())
+}
 
-    let rows = query.fetch_all(&*db).await?;
-    let mut values = Vec::new();
+/// Stops the stream `stream_id`, an `end` event with `cancelled` set is sent once it stopped.
+#[command]
+async fn cancel_stream(db_streams: State<'_, DbStreams>, stream_id: String) -> Result<()> {
+    if let Some(permits) = db_streams.0.lock().await.get(&stream_id) {
+        permits.close();
+    }
+    Ok(())
+}
 
-    for row in rows {
-        let mut value = HashMap::default();
-        for (i, column) in row.columns().iter().enumerate() {
-            let v = row.try_get_raw(i)?;
-            let v = crate::decode::to_json(v)?;
-            value.insert(column.name().to_string(), v);
+/// Opens a cursor over the rows of `query` and returns its id.
+///
+/// The cursor keeps a connection of the pool until it is closed with `close_cursor`,
+/// all of its rows were fetched, or it wasn't used for `idle_timeout` milliseconds.
+#[command]
+#[allow(clippy::too_many_arguments)]
+async fn open_cursor<R: Runtime>(
+    app: AppHandle<R>,
+    db_instances: State<'_, DbInstances>,
+    db_cursors: State<'_, DbCursors>,
+    codecs: State<'_, TypeCodecs>,
+    db: String,
+    query: String,
+    values: BindValues,
+    idle_timeout: Option<u64>,
+    blobs: Option<BlobEncoding>,
+) -> Result<String> {
+    let values = values.encode(&codecs)?;
+    let instance = db_instances.get(db.clone()).await?;
+    let options = instance.options.with_blobs(blobs);
+    let cursor = Cursor::open(instance.pool, query, values, options);
+    let idle_timeout = Duration::from_millis(idle_timeout.unwrap_or(5 * 60 * 1000));
+    Ok(db_cursors.insert(app, db, cursor, idle_timeout).await)
+}
+
+/// Fetches up to `n` rows of the cursor `cursor_id`, starting at the row index `offset`
+/// or right after the previously fetched page.
+///
+/// A cursor whose query failed is closed, so that later fetches don't report it as done.
+#[command]
+async fn fetch_cursor(
+    db_cursors: State<'_, DbCursors>,
+    cursor_id: String,
+    n: usize,
+    offset: Option<usize>,
+) -> Result<CursorPage> {
+    let cursor = db_cursors.get(cursor_id.clone()).await?;
+    let page = cursor.lock().await.fetch(n, offset).await;
+    if page.is_err() {
+        let _ = db_cursors.remove(cursor_id).await;
+    }
+    page
+}
+
+#[command]
+async fn close_cursor(db_cursors: State<'_, DbCursors>, cursor_id: String) -> Result<()> {
+    db_cursors.remove(cursor_id).await
+}
+
+/// Executes several statements in one IPC round trip and returns the outcome of each of them.
+///
+/// The batch runs in its own transaction which is committed once all statements ran,
+/// or inside of the transaction `transaction_id` when given. Unless `continue_on_error`
+/// is set, the first failing statement rolls back the whole batch.
+#[command]
+async fn execute_batch(
+    db_instances: State<'_, DbInstances>,
+    db_transactions: State<'_, DbTransactions>,
+    codecs: State<'_, TypeCodecs>,
+    db: String,
+    batch: Batch,
+    continue_on_error: Option<bool>,
+    transaction_id: Option<String>,
+) -> Result<Vec<BatchResult>> {
+    const SAVEPOINT: &str = "tauri_sql_batch";
+    let continue_on_error = continue_on_error.unwrap_or_default();
+    let batch = batch.encode(&codecs)?;
+
+    if let Some(id) = transaction_id {
+        let transaction = db_transactions.get(id.clone(), &db).await?;
+        let mut transaction = transaction.lock().await;
+        let open = transaction.as_mut().ok_or(Error::TransactionNotFound(id))?;
+
+        open.transaction.savepoint(SAVEPOINT).await?;
+        return match open.transaction.execute_batch(batch, continue_on_error).await {
+            Ok(results) => {
+                open.transaction.release_savepoint(SAVEPOINT).await?;
+                Ok(results)
+            }
+            Err(e) => {
+                open.transaction.rollback_to_savepoint(SAVEPOINT).await?;
+                Err(e)
+            }
+        };
+    }
+
+    let instance = db_instances.get(db).await?;
+    let mut transaction = instance.pool.begin().await?;
+    match transaction.execute_batch(batch, continue_on_error).await {
+        Ok(results) => {
+            transaction.commit().await?;
+            Ok(results)
+        }
+        Err(e) => {
+            transaction.rollback().await?;
+            Err(e)
         }
-        values.push(value);
     }
+}
+
+/// Begins a transaction on `db` and returns its id.
+///
+/// When `transaction_id` refers to an open transaction, a savepoint is set on it instead
+/// and the returned id commits (releases) or rolls back to that savepoint.
+///
+/// The transaction is rolled back if it is still open when the window of the calling webview is destroyed.
+#[command]
+async fn begin<R: Runtime>(
+    webview: Webview<R>,
+    db_instances: State<'_, DbInstances>,
+    db_transactions: State<'_, DbTransactions>,
+    db: String,
+    transaction_id: Option<String>,
+) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let window = webview.window().label().to_string();
+
+    if let Some(parent) = transaction_id {
+        let transaction = db_transactions.get(parent.clone(), &db).await?;
+        let mut open = transaction.lock().await;
+        let open = open.as_mut().ok_or(Error::TransactionNotFound(parent))?;
+
+        let name = OpenTransaction::savepoint_name(open.savepoints.len());
+        open.transaction.savepoint(&name).await?;
+        open.savepoints.push(id.clone());
+
+        db_transactions.0.lock().await.insert(
+            id.clone(),
+            TransactionHandle {
+                db,
+                window,
+                transaction: transaction.clone(),
+                nested: true,
+            },
+        );
+        return Ok(id);
+    }
+
+    let instance = db_instances.get(db.clone()).await?;
+    let transaction = instance.pool.begin().await?;
+
+    db_transactions.0.lock().await.insert(
+        id.clone(),
+        TransactionHandle {
+            db,
+            window,
+            transaction: Arc::new(Mutex::new(Some(OpenTransaction {
+                transaction,
+                savepoints: Vec::new(),
+            }))),
+            nested: false,
+        },
+    );
+    Ok(id)
+}
 
-    Ok(values)
+#[command]
+async fn commit(db_transactions: State<'_, DbTransactions>, transaction_id: String) -> Result<()> {
+    db_transactions.end(transaction_id, true).await
+}
+
+#[command]
+async fn rollback(
+    db_transactions: State<'_, DbTransactions>,
+    transaction_id: String,
+) -> Result<()> {
+    db_transactions.end(transaction_id, false).await
 }
 
 /// Tauri SQL plugin builder.
 #[derive(Default)]
 pub struct Builder {
   migrations: Option<HashMap<String, MigrationList>>,
+  codecs: Vec<(String, Box<dyn TypeCodec>)>,
 }
 
 impl Builder {
@@ -277,39 +647,64 @@ impl Builder {
       self
   }
 
+  /// Add a codec for the values of the SQL type `type_name`, see [`TypeCodec`].
+  #[must_use]
+  pub fn add_codec(mut self, type_name: &str, codec: impl TypeCodec) -> Self {
+      self.codecs.push((type_name.to_string(), Box::new(codec)));
+      self
+  }
+
   pub fn build<R: Runtime>(mut self) -> TauriPlugin<R, Option<PluginConfig>> {
+      let codecs = TypeCodecs::new(std::mem::take(&mut self.codecs));
       PluginBuilder::<R, Option<PluginConfig>>::new("sql")
           .js_init_script(include_str!("api-iife.js").to_string())
-          .invoke_handler(tauri::generate_handler![load, execute, select, close])
+          .invoke_handler(tauri::generate_handler![
+              load,
+              execute,
+              execute_batch,
+              select,
+              select_stream,
+              ack_stream,
+              cancel_stream,
+              open_cursor,
+              fetch_cursor,
+              close_cursor,
+              close,
+              begin,
+              commit,
+              rollback
+          ])
           .setup(|app, api| {
               let config = api.config().clone().unwrap_or_default();
-
-              #[cfg(feature = "sqlite")]
-              create_dir_all(app_path(app)).expect("problems creating App directory!");
+              let strict = StrictDatabases(config.strict.into_iter().collect());
 
               tauri::async_runtime::block_on(async move {
                   let instances = DbInstances::default();
                   let mut lock = instances.0.lock().await;
                   for db in config.preload {
-                      #[cfg(feature = "sqlite")]
-                      let fqdb = path_mapper(app_path(app), &db);
-                      #[cfg(not(feature = "sqlite"))]
-                      let fqdb = db.clone();
-
-                      if !Db::database_exists(&fqdb).await.unwrap_or(false) {
-                          Db::create_database(&fqdb).await?;
-                      }
-                      let pool = Pool::connect(&fqdb).await?;
+                      let pool = DbPool::connect(&db, app).await?;
 
-                      if let Some(migrations) = self.migrations.as_mut().unwrap().remove(&db) {
+                      if let Some(migrations) =
+                          self.migrations.as_mut().and_then(|mm| mm.remove(&db))
+                      {
                           let migrator = Migrator::new(migrations).await?;
-                          migrator.run(&pool).await?;
+                          pool.migrate(&migrator).await?;
                       }
-                      lock.insert(db, pool);
+                      let options = DecodeOptions {
+                          strict: strict.0.contains(&db),
+                          codecs: codecs.clone(),
+                          ..Default::default()
+                      };
+                      lock.insert(db, DbInstance { pool, options });
                   }
                   drop(lock);
 
                   app.manage(instances);
+                  app.manage(DbTransactions::default());
+                  app.manage(DbStreams::default());
+                  app.manage(DbCursors::default());
+                  app.manage(strict);
+                  app.manage(codecs);
                   app.manage(Migrations(Mutex::new(
                       self.migrations.take().unwrap_or_default(),
                   )));
@@ -317,16 +712,35 @@ impl Builder {
                   Ok(())
               })
           })
-          .on_event(|app, event| {
-              if let RunEvent::Exit = event {
+          .on_event(|app, event| match event {
+              RunEvent::WindowEvent {
+                  label,
+                  event: WindowEvent::Destroyed,
+                  ..
+              } => {
+                  let app = app.clone();
+                  let label = label.clone();
+                  tauri::async_runtime::spawn(async move {
+                      let transactions = &*app.state::<DbTransactions>();
+                      transactions
+                          .rollback_where(|handle| handle.window == label)
+                          .await;
+                  });
+              }
+              RunEvent::Exit => {
                   tauri::async_runtime::block_on(async move {
+                      let transactions = &*app.state::<DbTransactions>();
+                      transactions.rollback_where(|_| true).await;
+                      app.state::<DbCursors>().clear().await;
+
                       let instances = &*app.state::<DbInstances>();
                       let instances = instances.0.lock().await;
                       for value in instances.values() {
-                          value.close().await;
+                          value.pool.close().await;
                       }
                   });
               }
+              _ => {}
           })
           .build()
   }
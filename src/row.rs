@@ -0,0 +1,802 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use indexmap::IndexMap;
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value as JsonValue};
+
+use std::collections::{HashMap, HashSet};
+
+use tauri::ipc::InvokeResponseBody;
+
+#[cfg(feature = "mssql")]
+use crate::decode::mssql::Conversion;
+use crate::{
+    codec::TypeCodecs,
+    error::{Error, Result},
+    sql::{tokens, Token},
+};
+
+/// A decoded row, with its columns in the order of the query.
+pub(crate) type JsonRow = IndexMap<String, JsonValue>;
+
+/// What to do when several columns of a result set have the same name,
+/// such as the `id` columns of joined tables.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DuplicateColumns {
+    /// Fail the query with [`Error::DuplicateColumn`].
+    Error,
+    /// Rename the repeated columns to `column_table`, or `column_2` when the table is unknown.
+    #[default]
+    Suffix,
+    /// Group every column selected as `table.column` under a `table` key.
+    Nest,
+}
+
+/// How the values of `BLOB`, `BYTEA` and other binary columns are returned.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum BlobEncoding {
+    /// The bytes themselves: an array of octets in JSON, binary values with the
+    /// `msgpack` encoding of the columnar format.
+    #[default]
+    Raw,
+    /// A standard base64 string, with padding.
+    Base64,
+    /// A lowercase hexadecimal string.
+    Hex,
+}
+
+/// How decimals, and integers that a JavaScript number can't hold exactly, are returned.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum BigNumbers {
+    /// A string with every digit, such as `"12345678901234567890"` or `"10.50"`.
+    #[default]
+    String,
+    /// The closest number, losing precision.
+    Number,
+}
+
+/// How the values of date and time columns are returned.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DateTimeFormat {
+    /// An RFC 3339 string: `2024-01-31`, `13:45:00.5`, `2024-01-31T13:45:00` for values
+    /// without time zone and `2024-01-31T13:45:00Z` for the others.
+    #[default]
+    Rfc3339,
+    /// The number of milliseconds since the Unix epoch, values without time zone being
+    /// taken as UTC. Times are the number of milliseconds since midnight.
+    EpochMillis,
+    /// The RFC 3339 string in a `{ "$type", "value" }` object, which binds back to the
+    /// same SQL type.
+    Tagged,
+}
+
+/// How the rows of a connection are decoded, given when loading it.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct DecodeOptions {
+    pub(crate) duplicate_columns: DuplicateColumns,
+    pub(crate) blobs: BlobEncoding,
+    pub(crate) big_numbers: BigNumbers,
+    pub(crate) date_times: DateTimeFormat,
+    /// Parse the JSON returned by the `json` and `json_*` functions of SQLite.
+    pub(crate) json_functions: bool,
+    /// Fail the query when a value can't be decoded instead of returning `null`, set
+    /// from the `strict` databases of [`PluginConfig`](crate::PluginConfig).
+    #[serde(skip)]
+    pub(crate) strict: bool,
+    /// The codecs registered with the [`Builder`](crate::Builder).
+    #[serde(skip)]
+    pub(crate) codecs: TypeCodecs,
+    /// Whether each result column of the query is declared as `JSON` or `JSONB` in
+    /// SQLite, set per query.
+    #[cfg(feature = "sqlite")]
+    #[serde(skip)]
+    pub(crate) json_columns: Vec<bool>,
+    /// How the server converts the columns of an MSSQL query that sqlx can't read, set
+    /// per query.
+    #[cfg(feature = "mssql")]
+    #[serde(skip)]
+    pub(crate) conversion: Option<Conversion>,
+}
+
+impl DecodeOptions {
+    /// Returns these options with the overrides given to a single query.
+    pub(crate) fn with_blobs(&self, blobs: Option<BlobEncoding>) -> Self {
+        Self {
+            blobs: blobs.unwrap_or(self.blobs),
+            ..self.clone()
+        }
+    }
+
+    /// Returns these options for a SQLite query whose columns declared as JSON are
+    /// `json_columns`.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_json_columns(&self, json_columns: Vec<bool>) -> Self {
+        Self {
+            json_columns,
+            ..self.clone()
+        }
+    }
+
+    /// Returns these options for an MSSQL query run with `conversion`.
+    #[cfg(feature = "mssql")]
+    pub(crate) fn with_conversion(&self, conversion: Option<Conversion>) -> Self {
+        Self {
+            conversion,
+            ..self.clone()
+        }
+    }
+}
+
+/// The format of the rows returned by `select`.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SelectFormat {
+    /// One object per row, keyed by column name.
+    #[default]
+    Objects,
+    /// The column names once, then one array of values per row.
+    Columns,
+}
+
+/// How the result of `select` is encoded for the IPC response.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ResultEncoding {
+    #[default]
+    Json,
+    /// MessagePack, sending the values of binary columns in the columnar format as bytes.
+    Msgpack,
+}
+
+/// The name and database type of a column.
+#[derive(Serialize)]
+pub(crate) struct ColumnInfo {
+    name: String,
+    #[serde(rename = "type")]
+    type_name: String,
+    /// The key of the object the column is nested in with [`DuplicateColumns::Nest`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table: Option<String>,
+}
+
+impl ColumnInfo {
+    pub(crate) fn new(table: Option<&str>, name: &str, type_name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            type_name: type_name.to_string(),
+            table: table.map(String::from),
+        }
+    }
+}
+
+/// The rows returned by `select`, in the requested [`SelectFormat`].
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum SelectResult {
+    Objects(Vec<JsonRow>),
+    Columns {
+        columns: Vec<ColumnInfo>,
+        rows: Vec<Vec<JsonValue>>,
+    },
+}
+
+impl SelectResult {
+    /// Encodes the result as the body of the IPC response.
+    pub(crate) fn encode(&self, encoding: ResultEncoding) -> Result<InvokeResponseBody> {
+        match encoding {
+            ResultEncoding::Json => serde_json::to_string(self)
+                .map(InvokeResponseBody::Json)
+                .map_err(|e| Error::Encode(e.to_string())),
+            ResultEncoding::Msgpack => rmp_serde::to_vec_named(&Msgpack(self))
+                .map(InvokeResponseBody::Raw)
+                .map_err(|e| Error::Encode(e.to_string())),
+        }
+    }
+
+    pub(crate) fn empty(format: SelectFormat) -> Self {
+        match format {
+            SelectFormat::Objects => Self::Objects(Vec::new()),
+            SelectFormat::Columns => Self::Columns {
+                columns: Vec::new(),
+                rows: Vec::new(),
+            },
+        }
+    }
+}
+
+/// A [`SelectResult`] serialized for MessagePack.
+struct Msgpack<'a>(&'a SelectResult);
+
+impl Serialize for Msgpack<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let SelectResult::Columns { columns, rows } = self.0 else {
+            return self.0.serialize(serializer);
+        };
+
+        let binary: Vec<bool> = columns
+            .iter()
+            .map(|column| is_binary(&column.type_name))
+            .collect();
+        let rows: Vec<Vec<Cell<'_>>> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&binary)
+                    .map(|(value, binary)| Cell(value, *binary))
+                    .collect()
+            })
+            .collect();
+
+        let mut result = serializer.serialize_struct("SelectResult", 2)?;
+        result.serialize_field("columns", columns)?;
+        result.serialize_field("rows", &rows)?;
+        result.end()
+    }
+}
+
+/// A value of a column, serialized as bytes when the column is binary and the value
+/// was decoded to an array of octets.
+struct Cell<'a>(&'a JsonValue, bool);
+
+impl Serialize for Cell<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if let Cell(JsonValue::Array(items), true) = self {
+            let bytes: Option<Vec<u8>> = items
+                .iter()
+                .map(|item| item.as_u64().and_then(|n| u8::try_from(n).ok()))
+                .collect();
+            if let Some(bytes) = bytes {
+                return serializer.serialize_bytes(&bytes);
+            }
+        }
+        self.0.serialize(serializer)
+    }
+}
+
+/// Whether `type_name` is a binary string type of one of the drivers.
+fn is_binary(type_name: &str) -> bool {
+    const BINARY: &[&str] = &[
+        "BLOB",
+        "TINYBLOB",
+        "MEDIUMBLOB",
+        "LONGBLOB",
+        "BYTEA",
+        "BINARY",
+        "VARBINARY",
+        "IMAGE",
+    ];
+    BINARY
+        .iter()
+        .any(|name| type_name.eq_ignore_ascii_case(name))
+}
+
+/// Where a column goes in a decoded row.
+enum Key {
+    Column(String),
+    Nested(String, String),
+}
+
+/// What a result column is selected from, as written in the select list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ColumnSource {
+    /// A column of a table, named or expanded from a star.
+    Column,
+    /// A call to a `json` or `json_*` function.
+    JsonFunction,
+    /// Any other expression, or a column that couldn't be matched to the select list.
+    #[default]
+    Expression,
+}
+
+/// The keys of the columns of a result set, computed once per query.
+pub(crate) struct RowShape {
+    keys: Vec<Key>,
+    sources: Vec<ColumnSource>,
+}
+
+impl RowShape {
+    /// Computes the keys of `columns`, the result columns of `query`.
+    ///
+    /// The table of a column is only known when the select list names it as `table.column`
+    /// or `table.*`, columns of a plain `*` or of an expression have no table. `brackets`
+    /// tells whether `[...]` quotes identifiers in `query`.
+    ///
+    /// Nested tables share the top level of the row with the other columns, a table and
+    /// a column of the same name are told apart like duplicate columns.
+    pub(crate) fn new<'a>(
+        query: &str,
+        columns: impl ExactSizeIterator<Item = &'a str>,
+        duplicates: DuplicateColumns,
+        brackets: bool,
+    ) -> Result<Self> {
+        let (tables, sources) = column_sources(query, columns.len(), brackets);
+        let mut taken = HashSet::new();
+        // the top level key of each nested table
+        let mut nested = HashMap::new();
+        let mut keys = Vec::with_capacity(tables.len());
+
+        for (name, table) in columns.zip(tables) {
+            let duplicate = taken.contains(&(String::new(), name.to_string()));
+            keys.push(match (duplicates, table) {
+                (DuplicateColumns::Nest, Some(table)) => {
+                    let key = nested
+                        .entry(table)
+                        .or_insert_with_key(|table| unique(&mut taken, "", table))
+                        .clone();
+                    let column = unique(&mut taken, &key, name);
+                    Key::Nested(key, column)
+                }
+                (DuplicateColumns::Error, _) if duplicate => {
+                    return Err(Error::DuplicateColumn(name.to_string()))
+                }
+                (DuplicateColumns::Suffix, Some(table)) if duplicate => {
+                    Key::Column(unique(&mut taken, "", &format!("{name}_{table}")))
+                }
+                _ => Key::Column(unique(&mut taken, "", name)),
+            });
+        }
+
+        Ok(Self { keys, sources })
+    }
+
+    /// Returns what each column is selected from.
+    pub(crate) fn sources(&self) -> &[ColumnSource] {
+        &self.sources
+    }
+
+    /// Returns the key of each column, with the key of its table for nested columns.
+    pub(crate) fn names(&self) -> impl Iterator<Item = (Option<&str>, &str)> {
+        self.keys.iter().map(|key| match key {
+            Key::Column(name) => (None, name.as_str()),
+            Key::Nested(table, name) => (Some(table.as_str()), name.as_str()),
+        })
+    }
+
+    /// Builds a row from the decoded values of its columns.
+    pub(crate) fn row(&self, values: impl IntoIterator<Item = JsonValue>) -> JsonRow {
+        let mut row = JsonRow::with_capacity(self.keys.len());
+        for (key, value) in self.keys.iter().zip(values) {
+            match key {
+                Key::Column(column) => {
+                    row.insert(column.clone(), value);
+                }
+                Key::Nested(table, column) => {
+                    let nested = row
+                        .entry(table.clone())
+                        .or_insert_with(|| JsonValue::Object(Map::new()));
+                    if let JsonValue::Object(nested) = nested {
+                        nested.insert(column.clone(), value);
+                    }
+                }
+            }
+        }
+        row
+    }
+}
+
+/// Returns `name`, suffixed with `_2`, `_3`... if it is already taken in `scope`,
+/// the top level of the row or one of its tables.
+fn unique(taken: &mut HashSet<(String, String)>, scope: &str, name: &str) -> String {
+    let mut candidate = name.to_string();
+    let mut n = 1;
+    while !taken.insert((scope.to_string(), candidate.clone())) {
+        n += 1;
+        candidate = format!("{name}_{n}");
+    }
+    candidate
+}
+
+/// An item of the select list.
+struct SelectItem {
+    table: Option<String>,
+    source: ColumnSource,
+    /// Whether the item is `*` or `table.*`, expanding to an unknown number of columns.
+    star: bool,
+}
+
+/// Returns the table, when known, and the source of each of the `count` result columns of `query`.
+fn column_sources(
+    query: &str,
+    count: usize,
+    brackets: bool,
+) -> (Vec<Option<String>>, Vec<ColumnSource>) {
+    let items = select_items(query, brackets);
+    let stars = items.iter().filter(|item| item.star).count();
+
+    let mut tables = Vec::with_capacity(count);
+    let mut sources = Vec::with_capacity(count);
+    match stars {
+        0 if items.len() == count => {
+            for item in items {
+                tables.push(item.table);
+                sources.push(item.source);
+            }
+        }
+        // the columns a single star expands to are those not matched by the other items
+        1 if items.len() - 1 <= count => {
+            let expanded = count - (items.len() - 1);
+            for item in items {
+                let n = if item.star { expanded } else { 1 };
+                tables.extend(std::iter::repeat(item.table).take(n));
+                sources.extend(std::iter::repeat(item.source).take(n));
+            }
+        }
+        _ => {}
+    }
+    tables.resize(count, None);
+    sources.resize(count, ColumnSource::Expression);
+    (tables, sources)
+}
+
+/// Parses the select list of the outermost `SELECT` of `query`.
+fn select_items(query: &str, brackets: bool) -> Vec<SelectItem> {
+    const END: &[&str] = &[
+        "from",
+        "into",
+        "where",
+        "group",
+        "having",
+        "window",
+        "order",
+        "limit",
+        "offset",
+        "fetch",
+        "for",
+        "union",
+        "intersect",
+        "except",
+    ];
+
+    let mut tokens = tokens(query, brackets);
+    let mut depth = 0usize;
+    loop {
+        match tokens.next() {
+            None => return Vec::new(),
+            Some(Token::Punct('(' | '[')) => depth += 1,
+            Some(Token::Punct(')' | ']')) => depth = depth.saturating_sub(1),
+            Some(Token::Word(word)) if depth == 0 && word.eq_ignore_ascii_case("select") => break,
+            _ => {}
+        }
+    }
+
+    let mut items = vec![Vec::new()];
+    for token in tokens {
+        match token {
+            // array subscripts and constructors in Postgres
+            Token::Punct('(' | '[') => depth += 1,
+            Token::Punct(')' | ']') => depth = depth.saturating_sub(1),
+            Token::Punct(',') if depth == 0 => {
+                items.push(Vec::new());
+                continue;
+            }
+            Token::Punct(';') if depth == 0 => break,
+            Token::Word(word)
+                if depth == 0 && END.iter().any(|end| word.eq_ignore_ascii_case(end)) =>
+            {
+                break
+            }
+            _ => {}
+        }
+        if let Some(item) = items.last_mut() {
+            item.push(token);
+        }
+    }
+
+    if let Some(first) = items.first_mut() {
+        skip_modifiers(first);
+    }
+    items.iter().map(|tokens| select_item(tokens)).collect()
+}
+
+/// Removes the `DISTINCT`, `DISTINCT ON (...)`, `ALL` and `TOP n` modifiers from the first item.
+fn skip_modifiers(tokens: &mut Vec<Token<'_>>) {
+    let mut start = 0;
+    loop {
+        match tokens.get(start) {
+            Some(Token::Word(word))
+                if ["distinct", "all", "distinctrow"]
+                    .iter()
+                    .any(|m| word.eq_ignore_ascii_case(m)) =>
+            {
+                start += 1;
+                if matches!(tokens.get(start), Some(Token::Word(on)) if on.eq_ignore_ascii_case("on"))
+                {
+                    start = skip_group(tokens, start + 1);
+                }
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("top") => {
+                start = match tokens.get(start + 1) {
+                    Some(Token::Punct('(')) => skip_group(tokens, start + 1),
+                    _ => start + 2,
+                };
+            }
+            _ => break,
+        }
+    }
+    tokens.drain(..start.min(tokens.len()));
+}
+
+/// Returns the index following the parenthesized group starting at `start`.
+fn skip_group(tokens: &[Token<'_>], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i + 1;
+        }
+    }
+    tokens.len()
+}
+
+/// Parses `[schema.]table.column [[AS] alias]` and `[table.]*`, any other item has no table.
+///
+/// `json(...)` and `json_*(...)` items, optionally aliased, are calls to JSON functions.
+fn select_item(tokens: &[Token<'_>]) -> SelectItem {
+    let ident = |token: Option<&Token<'_>>| match token {
+        Some(Token::Word(word)) => Some(word.to_string()),
+        Some(Token::Quoted(quoted)) => Some(quoted.to_string()),
+        _ => None,
+    };
+
+    let mut path = Vec::new();
+    let mut i = 0;
+    let mut star = false;
+    loop {
+        match tokens.get(i) {
+            Some(Token::Punct('*')) => {
+                star = true;
+                i += 1;
+                break;
+            }
+            token => match ident(token) {
+                Some(name) => path.push(name),
+                None => break,
+            },
+        }
+        i += 1;
+        if tokens.get(i) != Some(&Token::Punct('.')) {
+            break;
+        }
+        i += 1;
+    }
+
+    let is_as =
+        |token: &Token<'_>| matches!(token, Token::Word(word) if word.eq_ignore_ascii_case("as"));
+    // whether `rest` is empty or an alias
+    let only_alias = |rest: &[Token<'_>], star: bool| {
+        let aliased = match rest {
+            [] => true,
+            [alias] | [Token::Word(_), alias] if !star => ident(Some(alias)).is_some(),
+            _ => false,
+        };
+        aliased && (rest.len() < 2 || is_as(&rest[0]))
+    };
+
+    let aliased = only_alias(&tokens[i.min(tokens.len())..], star);
+    let json_call = match tokens {
+        [Token::Word(function), Token::Punct('('), ..] => {
+            let function = function.to_ascii_lowercase();
+            (function == "json" || function.starts_with("json_"))
+                && only_alias(&tokens[skip_group(tokens, 1)..], false)
+        }
+        _ => false,
+    };
+
+    let source = if json_call {
+        ColumnSource::JsonFunction
+    } else if aliased && (star || !path.is_empty()) {
+        ColumnSource::Column
+    } else {
+        ColumnSource::Expression
+    };
+    let table_index = if star {
+        path.len().checked_sub(1)
+    } else {
+        path.len().checked_sub(2)
+    };
+    SelectItem {
+        table: table_index.filter(|_| aliased).map(|i| path.swap_remove(i)),
+        source,
+        star: star && aliased,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn shape(query: &str, columns: &[&str], duplicates: DuplicateColumns) -> Result<JsonValue> {
+        let shape = RowShape::new(query, columns.iter().copied(), duplicates, false)?;
+        let row = shape.row((1..).map(JsonValue::from));
+        Ok(serde_json::to_value(row).unwrap())
+    }
+
+    fn item(query: &str) -> (Option<String>, ColumnSource, bool) {
+        let item = select_items(query, true).remove(0);
+        (item.table, item.source, item.star)
+    }
+
+    fn tables(query: &str, brackets: bool) -> Vec<Option<String>> {
+        select_items(query, brackets)
+            .into_iter()
+            .map(|item| item.table)
+            .collect()
+    }
+
+    const JOIN: &str = "SELECT a.id, b.id, b.name FROM a JOIN b ON b.a_id = a.id";
+
+    #[test]
+    fn duplicates_fail_under_error() {
+        let error = shape(JOIN, &["id", "id", "name"], DuplicateColumns::Error).unwrap_err();
+        assert!(matches!(error, Error::DuplicateColumn(name) if name == "id"));
+
+        let row = shape(
+            "SELECT id, name FROM a",
+            &["id", "name"],
+            DuplicateColumns::Error,
+        );
+        assert_eq!(row.unwrap(), json!({"id": 1, "name": 2}));
+    }
+
+    #[test]
+    fn duplicates_are_suffixed() {
+        let row = shape(JOIN, &["id", "id", "name"], DuplicateColumns::Suffix).unwrap();
+        assert_eq!(row, json!({"id": 1, "id_b": 2, "name": 3}));
+
+        // the table of a plain star is unknown
+        let row = shape(
+            "SELECT * FROM a JOIN b",
+            &["id", "id", "id"],
+            DuplicateColumns::Suffix,
+        );
+        assert_eq!(row.unwrap(), json!({"id": 1, "id_2": 2, "id_3": 3}));
+
+        // a suffixed name that is taken gets a number
+        let row = shape(
+            "SELECT a.id, b.id, 0 AS id_b FROM a, b",
+            &["id", "id", "id_b"],
+            DuplicateColumns::Suffix,
+        );
+        assert_eq!(row.unwrap(), json!({"id": 1, "id_b": 2, "id_b_2": 3}));
+    }
+
+    #[test]
+    fn columns_with_a_table_are_nested() {
+        let row = shape(
+            "SELECT a.id, b.*, count(*) AS n, b.id FROM a JOIN b ON b.a_id = a.id",
+            &["id", "id", "name", "n", "id"],
+            DuplicateColumns::Nest,
+        )
+        .unwrap();
+        assert_eq!(
+            row,
+            json!({"a": {"id": 1}, "b": {"id": 2, "name": 3, "id_2": 5}, "n": 4})
+        );
+    }
+
+    #[test]
+    fn columnar_columns_keep_their_table() {
+        let shape = RowShape::new(
+            JOIN,
+            ["id", "id", "name"].into_iter(),
+            DuplicateColumns::Nest,
+            false,
+        )
+        .unwrap();
+        let columns: Vec<_> = shape
+            .names()
+            .map(|(table, name)| ColumnInfo::new(table, name, "INTEGER"))
+            .collect();
+        assert_eq!(
+            serde_json::to_value(columns).unwrap(),
+            json!([
+                {"name": "id", "type": "INTEGER", "table": "a"},
+                {"name": "id", "type": "INTEGER", "table": "b"},
+                {"name": "name", "type": "INTEGER", "table": "b"},
+            ])
+        );
+
+        let shape = RowShape::new(
+            JOIN,
+            ["id", "id", "name"].into_iter(),
+            DuplicateColumns::Suffix,
+            false,
+        )
+        .unwrap();
+        let columns: Vec<_> = shape.names().collect();
+        assert_eq!(columns, [(None, "id"), (None, "id_b"), (None, "name")]);
+    }
+
+    #[test]
+    fn tables_and_columns_of_the_same_name_are_told_apart() {
+        let row = shape(
+            "SELECT x AS b, b.id FROM b",
+            &["b", "id"],
+            DuplicateColumns::Nest,
+        );
+        assert_eq!(row.unwrap(), json!({"b": 1, "b_2": {"id": 2}}));
+
+        let row = shape(
+            "SELECT b.id, x AS b, b.name FROM b",
+            &["id", "b", "name"],
+            DuplicateColumns::Nest,
+        );
+        assert_eq!(row.unwrap(), json!({"b": {"id": 1, "name": 3}, "b_2": 2}));
+    }
+
+    #[test]
+    fn brackets_are_quotes_or_subscripts() {
+        let query = "SELECT arr[1], t.id, ARRAY[t.a, t.b] AS ab FROM t";
+        assert_eq!(tables(query, false), [None, Some("t".into()), None]);
+
+        let query = "SELECT [t].[id], [a, b].c FROM t";
+        assert_eq!(tables(query, true), [Some("t".into()), Some("a, b".into())]);
+    }
+
+    #[test]
+    fn select_items_know_their_table() {
+        assert_eq!(
+            item("SELECT t.c FROM t"),
+            (Some("t".into()), ColumnSource::Column, false)
+        );
+        assert_eq!(
+            item("SELECT s.t.c AS x FROM s.t"),
+            (Some("t".into()), ColumnSource::Column, false)
+        );
+        assert_eq!(
+            item("SELECT \"t\".\"c\" x FROM t"),
+            (Some("t".into()), ColumnSource::Column, false)
+        );
+        assert_eq!(
+            item("SELECT t.* FROM t"),
+            (Some("t".into()), ColumnSource::Column, true)
+        );
+        assert_eq!(item("SELECT * FROM t"), (None, ColumnSource::Column, true));
+        assert_eq!(item("SELECT c FROM t"), (None, ColumnSource::Column, false));
+    }
+
+    #[test]
+    fn select_items_skip_modifiers() {
+        assert_eq!(
+            item("SELECT DISTINCT ON (t.a) t.c FROM t"),
+            (Some("t".into()), ColumnSource::Column, false)
+        );
+        assert_eq!(
+            item("SELECT TOP (5) t.c FROM t"),
+            (Some("t".into()), ColumnSource::Column, false)
+        );
+    }
+
+    #[test]
+    fn expressions_and_json_calls_have_no_table() {
+        assert_eq!(
+            item("SELECT t.c + 1 FROM t"),
+            (None, ColumnSource::Expression, false)
+        );
+        assert_eq!(
+            item("SELECT (SELECT t.c FROM t) AS c"),
+            (None, ColumnSource::Expression, false)
+        );
+        assert_eq!(
+            item("SELECT json_object('a', t.c) AS o FROM t"),
+            (None, ColumnSource::JsonFunction, false)
+        );
+        assert_eq!(
+            item("SELECT json_extract(t.c, '$.a') -> 'b' FROM t"),
+            (None, ColumnSource::Expression, false)
+        );
+    }
+}
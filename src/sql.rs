@@ -0,0 +1,128 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Helpers to scan SQL text without a full parser, skipping literals and comments.
+
+use std::ops::Range;
+
+/// Returns the length of the UTF-8 character starting with the byte `c`.
+pub(crate) fn utf8_len(c: u8) -> usize {
+    match c.leading_ones() {
+        0 => 1,
+        n => n as usize,
+    }
+}
+
+pub(crate) fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+pub(crate) fn ident_len(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|c| is_ident(**c)).count()
+}
+
+/// Returns the index following the first `pattern` found from `start`, or the end of `bytes`.
+pub(crate) fn find(bytes: &[u8], start: usize, pattern: &[u8]) -> usize {
+    bytes[start.min(bytes.len())..]
+        .windows(pattern.len())
+        .position(|w| w == pattern)
+        .map_or(bytes.len(), |p| start + p + pattern.len())
+}
+
+/// Returns the `$tag$` opening a postgres dollar-quoted string at `start`, if any.
+pub(crate) fn dollar_quote_tag(bytes: &[u8], start: usize) -> Option<&[u8]> {
+    let end = start + 1 + ident_len(&bytes[start + 1..]);
+    let starts_with_digit = bytes.get(start + 1).is_some_and(u8::is_ascii_digit);
+    (!starts_with_digit && bytes.get(end) == Some(&b'$')).then(|| &bytes[start..=end])
+}
+
+/// A token of SQL text, see [`tokens`].
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    /// An unquoted identifier or keyword.
+    Word(&'a str),
+    /// A quoted identifier, without its quotes.
+    Quoted(&'a str),
+    /// A string or number literal.
+    Literal,
+    Punct(char),
+}
+
+/// Splits `query` in tokens, skipping whitespace and comments.
+///
+/// With `brackets`, `[...]` is a quoted identifier, otherwise brackets are punctuation.
+pub(crate) fn tokens(query: &str, brackets: bool) -> impl Iterator<Item = Token<'_>> {
+    spanned_tokens(query, brackets).map(|(_, token)| token)
+}
+
+/// Splits `query` in tokens like [`tokens`], with the range of `query` each one spans.
+pub(crate) fn spanned_tokens(
+    query: &str,
+    brackets: bool,
+) -> impl Iterator<Item = (Range<usize>, Token<'_>)> {
+    let bytes = query.as_bytes();
+    let mut i = 0;
+    std::iter::from_fn(move || loop {
+        let start = i;
+        let c = *bytes.get(i)?;
+        let next = bytes.get(i + 1).copied();
+        let token = match c {
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if next == Some(b'-') => {
+                i = find(bytes, i + 2, b"\n");
+                continue;
+            }
+            b'/' if next == Some(b'*') => {
+                i = find(bytes, i + 2, b"*/");
+                continue;
+            }
+            b'\'' => {
+                i = find(bytes, i + 1, b"'");
+                Token::Literal
+            }
+            b'"' | b'`' | b'[' if c != b'[' || brackets => {
+                let close = if c == b'[' { b']' } else { c };
+                i = find(bytes, i + 1, &[close]);
+                let end = if i > start + 1 && bytes[i - 1] == close {
+                    i - 1
+                } else {
+                    i
+                };
+                Token::Quoted(&query[start + 1..end])
+            }
+            b'$' => match dollar_quote_tag(bytes, i) {
+                Some(tag) => {
+                    i = find(bytes, i + tag.len(), tag);
+                    Token::Literal
+                }
+                None => {
+                    i += 1;
+                    Token::Punct('$')
+                }
+            },
+            c if c.is_ascii_digit() => {
+                i += bytes[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == b'.')
+                    .count();
+                Token::Literal
+            }
+            c if is_ident(c) || !c.is_ascii() => {
+                i += bytes[i..]
+                    .iter()
+                    .take_while(|c| is_ident(**c) || **c == b'$' || !c.is_ascii())
+                    .count();
+                Token::Word(&query[start..i])
+            }
+            c => {
+                i += utf8_len(c);
+                Token::Punct(c as char)
+            }
+        };
+        return Some((start..i, token));
+    })
+}
@@ -0,0 +1,1201 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+#[cfg(feature = "sqlite")]
+use std::{fs::create_dir_all, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "postgres"))]
+use sqlx::migrate::MigrateDatabase;
+use sqlx::{
+    database::{HasArguments, HasValueRef},
+    migrate::Migrator,
+    query::Query,
+    Column, ColumnIndex, Database, Executor, IntoArguments, Pool, Row, Transaction, TypeInfo,
+    ValueRef,
+};
+#[cfg(feature = "sqlite")]
+use tauri::Manager;
+use tauri::{AppHandle, Runtime};
+
+use tokio::sync::mpsc;
+
+use std::future::poll_fn;
+
+#[cfg(feature = "mssql")]
+use crate::decode::mssql::MssqlType;
+#[cfg(feature = "mssql")]
+use sqlx::Mssql;
+#[cfg(feature = "mysql")]
+use sqlx::MySql;
+#[cfg(feature = "sqlite")]
+use sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+use sqlx::{
+    encode::IsNull,
+    postgres::{types::Oid, PgArgumentBuffer, PgTypeInfo},
+    Postgres,
+};
+#[cfg(feature = "mssql")]
+use time::format_description::well_known::Rfc3339;
+
+#[cfg(feature = "mssql")]
+use crate::bind::{DATE, DATE_TIME, TIME};
+
+use crate::{
+    bind::{BindValue, BindValues, ParamStyle},
+    codec::TypeCodecs,
+    error::{Error, Result},
+    row::{ColumnInfo, ColumnSource, DecodeOptions, JsonRow, RowShape, SelectFormat, SelectResult},
+};
+
+/// The last inserted `id` reported by the driver.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum LastInsertId {
+    #[cfg(feature = "sqlite")]
+    Sqlite(i64),
+    #[cfg(feature = "mysql")]
+    MySql(u64),
+    #[cfg(feature = "postgres")]
+    Postgres(u64), // Always returns 0 in postgres
+    #[cfg(feature = "mssql")]
+    Mssql(u64), // Always returns 0 in mssql
+}
+
+type DbQuery<'q, DB> = Query<'q, DB, <DB as HasArguments<'q>>::Arguments>;
+
+/// Driver specific glue used by the generic query helpers.
+pub(crate) trait Driver: Database {
+    /// The syntax of the driver's positional parameters.
+    const PARAM_STYLE: ParamStyle;
+
+    /// Whether `@name` is a variable of the database, MySQL user variables and T-SQL
+    /// locals, so that only the names with a value are named parameters.
+    const AT_VARIABLES: bool = false;
+
+    /// Whether `[name]` is a quoted identifier, as in SQLite and MSSQL, rather than the
+    /// array syntax of Postgres.
+    const BRACKET_QUOTES: bool = false;
+
+    /// What the decoder reads of the type of a column, worked out once per result set.
+    type ColumnType;
+
+    /// Binds `value` as the next positional parameter of `query`.
+    fn bind(query: DbQuery<'_, Self>, value: BindValue) -> Result<DbQuery<'_, Self>>;
+
+    /// Returns the [`Driver::ColumnType`] of a column of a result set.
+    fn column_type(column: &Self::Column, options: &DecodeOptions) -> Self::ColumnType;
+
+    /// Decodes a single column value with the driver's decoder in `crate::decode`.
+    ///
+    /// `column_type` is the type of the column, which differs from the type of
+    /// `value` in SQLite where every value has its own storage class. `source` is
+    /// what the column is selected from.
+    fn to_json(
+        value: <Self as HasValueRef<'_>>::ValueRef,
+        column_type: &Self::ColumnType,
+        source: ColumnSource,
+        options: &DecodeOptions,
+    ) -> Result<JsonValue>;
+
+    /// Returns the `(rowsAffected, lastInsertId)` pair of an executed statement.
+    fn query_result(result: Self::QueryResult) -> (u64, LastInsertId);
+
+    /// Returns the SQL name of a column type.
+    fn type_name(type_info: &Self::TypeInfo) -> &str {
+        type_info.name()
+    }
+
+    /// Returns the SQL name of the type of a column read as `column_type`, when it
+    /// isn't the type of the column sqlx reports.
+    fn column_type_name(_column_type: &Self::ColumnType) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the query run for `sql`, which MSSQL wraps in a conversion of the
+    /// columns sqlx can't read.
+    fn executed_query<'q>(sql: &'q str, _options: &'q DecodeOptions) -> &'q str {
+        sql
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Driver for Sqlite {
+    const PARAM_STYLE: ParamStyle = ParamStyle::QuestionMark;
+    const BRACKET_QUOTES: bool = true;
+
+    type ColumnType = crate::decode::sqlite::ColumnType;
+
+    fn bind(query: DbQuery<'_, Self>, value: BindValue) -> Result<DbQuery<'_, Self>> {
+        Ok(match value {
+            BindValue::Null => query.bind(None::<String>),
+            BindValue::Bool(b) => query.bind(b),
+            BindValue::Int(i) => query.bind(i),
+            BindValue::Float(f) => query.bind(f),
+            BindValue::Text(s) => query.bind(s),
+            BindValue::Bytes(b) => query.bind(b),
+            BindValue::Json(v) => query.bind(v),
+            // stored as text, the way SQLite users usually keep them
+            BindValue::Uuid(u) => query.bind(u.to_string()),
+            BindValue::Date(d) => query.bind(d),
+            BindValue::Time(t) => query.bind(t),
+            BindValue::DateTime(dt) => query.bind(dt),
+            BindValue::Timestamp(t) => query.bind(t),
+            BindValue::Decimal(d) => query.bind(d.to_string()),
+            BindValue::Geometry(g) => query.bind(g.to_spatialite()),
+            BindValue::Array(_) => return Err(native_arrays_unsupported("sqlite")),
+        })
+    }
+
+    fn column_type(column: &Self::Column, options: &DecodeOptions) -> Self::ColumnType {
+        crate::decode::sqlite::ColumnType {
+            type_info: column.type_info().clone(),
+            json: options.json_columns.get(column.ordinal()) == Some(&true),
+        }
+    }
+
+    fn to_json(
+        value: <Self as HasValueRef<'_>>::ValueRef,
+        column_type: &Self::ColumnType,
+        source: ColumnSource,
+        options: &DecodeOptions,
+    ) -> Result<JsonValue> {
+        crate::decode::sqlite::to_json(value, column_type, source, options)
+    }
+
+    fn query_result(result: Self::QueryResult) -> (u64, LastInsertId) {
+        (
+            result.rows_affected(),
+            LastInsertId::Sqlite(result.last_insert_rowid()),
+        )
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl Driver for MySql {
+    const PARAM_STYLE: ParamStyle = ParamStyle::QuestionMark;
+    const AT_VARIABLES: bool = true;
+
+    type ColumnType = ();
+
+    fn bind(query: DbQuery<'_, Self>, value: BindValue) -> Result<DbQuery<'_, Self>> {
+        Ok(match value {
+            BindValue::Null => query.bind(None::<String>),
+            BindValue::Bool(b) => query.bind(b),
+            BindValue::Int(i) => query.bind(i),
+            BindValue::Float(f) => query.bind(f),
+            BindValue::Text(s) => query.bind(s),
+            BindValue::Bytes(b) => query.bind(b),
+            BindValue::Json(v) => query.bind(v),
+            // MySQL has no uuid type, they are usually kept in CHAR(36) columns
+            BindValue::Uuid(u) => query.bind(u.to_string()),
+            BindValue::Date(d) => query.bind(d),
+            BindValue::Time(t) => query.bind(t),
+            BindValue::DateTime(dt) => query.bind(dt),
+            BindValue::Timestamp(t) => query.bind(t),
+            BindValue::Decimal(d) => query.bind(d),
+            BindValue::Geometry(g) => query.bind(g.to_mysql()),
+            BindValue::Array(_) => return Err(native_arrays_unsupported("mysql")),
+        })
+    }
+
+    fn column_type(_: &Self::Column, _: &DecodeOptions) -> Self::ColumnType {}
+
+    fn to_json(
+        value: <Self as HasValueRef<'_>>::ValueRef,
+        _: &Self::ColumnType,
+        _: ColumnSource,
+        options: &DecodeOptions,
+    ) -> Result<JsonValue> {
+        crate::decode::mysql::to_json(value, options)
+    }
+
+    fn query_result(result: Self::QueryResult) -> (u64, LastInsertId) {
+        (
+            result.rows_affected(),
+            LastInsertId::MySql(result.last_insert_id()),
+        )
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Driver for Postgres {
+    const PARAM_STYLE: ParamStyle = ParamStyle::Dollar;
+
+    type ColumnType = ();
+
+    fn bind(query: DbQuery<'_, Self>, value: BindValue) -> Result<DbQuery<'_, Self>> {
+        Ok(match value {
+            BindValue::Null => query.bind(UntypedNull),
+            BindValue::Bool(b) => query.bind(b),
+            BindValue::Int(i) => query.bind(i),
+            BindValue::Float(f) => query.bind(f),
+            BindValue::Text(s) => query.bind(s),
+            BindValue::Bytes(b) => query.bind(b),
+            BindValue::Json(v) => query.bind(v),
+            BindValue::Uuid(u) => query.bind(u),
+            BindValue::Date(d) => query.bind(d),
+            BindValue::Time(t) => query.bind(t),
+            BindValue::DateTime(dt) => query.bind(dt),
+            BindValue::Timestamp(t) => query.bind(t),
+            BindValue::Decimal(d) => query.bind(d),
+            BindValue::Geometry(g) => query.bind(Untyped(g.to_ewkb())),
+            BindValue::Array(items) => bind_array(query, items)?,
+        })
+    }
+
+    fn column_type(_: &Self::Column, _: &DecodeOptions) -> Self::ColumnType {}
+
+    fn to_json(
+        value: <Self as HasValueRef<'_>>::ValueRef,
+        _: &Self::ColumnType,
+        _: ColumnSource,
+        options: &DecodeOptions,
+    ) -> Result<JsonValue> {
+        crate::decode::postgres::to_json(value, options)
+    }
+
+    fn query_result(result: Self::QueryResult) -> (u64, LastInsertId) {
+        (result.rows_affected(), LastInsertId::Postgres(0))
+    }
+}
+
+/// Binds `items` as a Postgres array of the type of its first non-null element.
+///
+/// Empty arrays and arrays of nulls are bound as `TEXT[]`.
+#[cfg(feature = "postgres")]
+fn bind_array(
+    query: DbQuery<'_, Postgres>,
+    items: Vec<BindValue>,
+) -> Result<DbQuery<'_, Postgres>> {
+    macro_rules! elements {
+        ($variant:ident $(, $other:ident => $convert:expr)?) => {
+            items
+                .into_iter()
+                .map(|item| match item {
+                    BindValue::Null => Ok(None),
+                    BindValue::$variant(v) => Ok(Some(v)),
+                    $(BindValue::$other(v) => Ok(Some($convert(v))),)?
+                    item => Err(Error::InvalidBindValue(format!(
+                        "array elements must have the same type, got {item:?}"
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+    }
+
+    let has_floats = items.iter().any(|item| matches!(item, BindValue::Float(_)));
+    Ok(
+        match items.iter().find(|item| !matches!(item, BindValue::Null)) {
+            None | Some(BindValue::Text(_)) => query.bind(elements!(Text)),
+            Some(BindValue::Bool(_)) => query.bind(elements!(Bool)),
+            Some(BindValue::Int(_) | BindValue::Float(_)) if has_floats => {
+                query.bind(elements!(Float, Int => |i| i as f64))
+            }
+            Some(BindValue::Int(_)) => query.bind(elements!(Int)),
+            Some(BindValue::Bytes(_)) => query.bind(elements!(Bytes)),
+            Some(BindValue::Json(_)) => query.bind(elements!(Json)),
+            Some(BindValue::Uuid(_)) => query.bind(elements!(Uuid)),
+            Some(BindValue::Date(_)) => query.bind(elements!(Date)),
+            Some(BindValue::Time(_)) => query.bind(elements!(Time)),
+            Some(BindValue::DateTime(_)) => query.bind(elements!(DateTime)),
+            Some(BindValue::Timestamp(_)) => query.bind(elements!(Timestamp)),
+            Some(BindValue::Decimal(_)) => query.bind(elements!(Decimal)),
+            Some(BindValue::Array(_)) => {
+                return Err(Error::InvalidBindValue("arrays can't be nested".into()))
+            }
+            // floats and NULLs are handled above, kept for exhaustiveness
+            Some(item @ (BindValue::Float(_) | BindValue::Null)) => {
+                return Err(Error::InvalidBindValue(format!(
+                    "unsupported array element {item:?}"
+                )))
+            }
+            Some(BindValue::Geometry(_)) => {
+                return Err(Error::InvalidBindValue(
+                    "arrays of geometries are not supported".into(),
+                ))
+            }
+        },
+    )
+}
+
+/// A NULL parameter whose type is left for the server to infer from the query.
+#[cfg(feature = "postgres")]
+struct UntypedNull;
+
+#[cfg(feature = "postgres")]
+impl sqlx::Type<Postgres> for UntypedNull {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl sqlx::Encode<'_, Postgres> for UntypedNull {
+    fn encode_by_ref(&self, _: &mut PgArgumentBuffer) -> IsNull {
+        IsNull::Yes
+    }
+}
+
+/// A value in the binary format of a type left for the server to infer, such as the
+/// EWKB read by the PostGIS `geometry` and `geography` types.
+#[cfg(feature = "postgres")]
+struct Untyped(Vec<u8>);
+
+#[cfg(feature = "postgres")]
+impl sqlx::Type<Postgres> for Untyped {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl sqlx::Encode<'_, Postgres> for Untyped {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        buf.extend_from_slice(&self.0);
+        IsNull::No
+    }
+}
+
+#[cfg(feature = "mssql")]
+impl Driver for Mssql {
+    const PARAM_STYLE: ParamStyle = ParamStyle::AtP;
+    const AT_VARIABLES: bool = true;
+    const BRACKET_QUOTES: bool = true;
+
+    type ColumnType = MssqlType;
+
+    fn bind(query: DbQuery<'_, Self>, value: BindValue) -> Result<DbQuery<'_, Self>> {
+        // sqlx only encodes booleans, numbers and strings for MSSQL, other types are
+        // sent as their text representation and converted by the server.
+        Ok(match value {
+            BindValue::Null => query.bind(None::<String>),
+            BindValue::Bool(b) => query.bind(b),
+            BindValue::Int(i) => query.bind(i),
+            BindValue::Float(f) => query.bind(f),
+            BindValue::Text(s) => query.bind(s),
+            BindValue::Bytes(_) => {
+                return Err(Error::InvalidBindValue(
+                    "bytes are not supported by the mssql driver".into(),
+                ))
+            }
+            BindValue::Json(v) => query.bind(v.to_string()),
+            BindValue::Uuid(u) => query.bind(u.to_string()),
+            BindValue::Date(d) => query.bind(formatted(d.format(DATE))?),
+            BindValue::Time(t) => query.bind(formatted(t.format(TIME))?),
+            BindValue::DateTime(dt) => query.bind(formatted(dt.format(DATE_TIME))?),
+            BindValue::Timestamp(t) => query.bind(formatted(t.format(&Rfc3339))?),
+            BindValue::Decimal(d) => query.bind(d.to_string()),
+            BindValue::Geometry(_) => {
+                return Err(Error::InvalidBindValue(
+                    "geometries are not supported by the mssql driver".into(),
+                ))
+            }
+            BindValue::Array(_) => return Err(native_arrays_unsupported("mssql")),
+        })
+    }
+
+    fn column_type(column: &Self::Column, options: &DecodeOptions) -> Self::ColumnType {
+        let converted = options
+            .conversion
+            .as_ref()
+            .and_then(|conversion| conversion.columns.get(column.ordinal()).copied().flatten());
+        converted.unwrap_or_else(|| MssqlType::of(column.type_info()))
+    }
+
+    fn to_json(
+        value: <Self as HasValueRef<'_>>::ValueRef,
+        column_type: &Self::ColumnType,
+        _: ColumnSource,
+        options: &DecodeOptions,
+    ) -> Result<JsonValue> {
+        crate::decode::mssql::to_json(value, *column_type, options)
+    }
+
+    fn query_result(result: Self::QueryResult) -> (u64, LastInsertId) {
+        (result.rows_affected(), LastInsertId::Mssql(0))
+    }
+
+    fn type_name(type_info: &Self::TypeInfo) -> &str {
+        MssqlType::of(type_info).name()
+    }
+
+    fn column_type_name(column_type: &Self::ColumnType) -> Option<&'static str> {
+        Some(column_type.name())
+    }
+
+    fn executed_query<'q>(sql: &'q str, options: &'q DecodeOptions) -> &'q str {
+        options
+            .conversion
+            .as_ref()
+            .map_or(sql, |conversion| &conversion.query)
+    }
+}
+
+/// Rewrites `query` to positional parameters and describes its columns on `conn`,
+/// returning the options to run it with for the server to convert the columns sqlx
+/// can't read.
+#[cfg(feature = "mssql")]
+async fn mssql_conversion(
+    conn: &mut sqlx::MssqlConnection,
+    query: &str,
+    values: BindValues,
+    options: &DecodeOptions,
+) -> Result<(String, BindValues, DecodeOptions)> {
+    let (sql, values) = values.into_positional(
+        query,
+        Mssql::PARAM_STYLE,
+        Mssql::AT_VARIABLES,
+        Mssql::BRACKET_QUOTES,
+    )?;
+    let conversion = crate::decode::mssql::conversion(conn, &sql, &values).await?;
+    Ok((
+        sql.into_owned(),
+        BindValues::Positional(values),
+        options.with_conversion(conversion),
+    ))
+}
+
+/// Returns a date or time formatted for the server to convert.
+#[cfg(feature = "mssql")]
+fn formatted(text: std::result::Result<String, time::error::Format>) -> Result<String> {
+    text.map_err(|e| Error::InvalidBindValue(format!("can't format the date: {e}")))
+}
+
+#[cfg(any(feature = "sqlite", feature = "mysql", feature = "mssql"))]
+fn native_arrays_unsupported(driver: &str) -> Error {
+    Error::InvalidBindValue(format!(
+        "native arrays are not supported by the {driver} driver"
+    ))
+}
+
+/// Prepares `query` with the positional `values` bound as its parameters.
+fn bind_values<DB: Driver>(query: &str, values: Vec<JsonValue>) -> Result<DbQuery<'_, DB>> {
+    let mut query = sqlx::query::<DB>(query);
+    for value in values {
+        query = DB::bind(query, BindValue::try_from(value)?)?;
+    }
+    Ok(query)
+}
+
+/// Runs `query` on `executor` and returns `(rowsAffected, lastInsertId)`.
+pub(crate) async fn execute<'c, DB, E>(
+    executor: E,
+    query: &str,
+    values: BindValues,
+) -> Result<(u64, LastInsertId)>
+where
+    DB: Driver,
+    E: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    let (sql, values) =
+        values.into_positional(query, DB::PARAM_STYLE, DB::AT_VARIABLES, DB::BRACKET_QUOTES)?;
+    let query = bind_values::<DB>(&sql, values)?;
+
+    let result = query.execute(executor).await?;
+    Ok(DB::query_result(result))
+}
+
+/// Runs `query` on `executor` and decodes every returned row in the given `format`.
+pub(crate) async fn select<'c, DB, E>(
+    executor: E,
+    query: &str,
+    values: BindValues,
+    options: &DecodeOptions,
+    format: SelectFormat,
+) -> Result<SelectResult>
+where
+    DB: Driver,
+    E: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let (sql, values) =
+        values.into_positional(query, DB::PARAM_STYLE, DB::AT_VARIABLES, DB::BRACKET_QUOTES)?;
+    let query = bind_values::<DB>(DB::executed_query(&sql, options), values)?;
+
+    let rows = query.fetch_all(executor).await?;
+    let Some(first) = rows.first() else {
+        return Ok(SelectResult::empty(format));
+    };
+
+    let shape = row_shape::<DB>(&sql, first, options)?;
+    match format {
+        SelectFormat::Objects => {
+            let rows = rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| row_to_json::<DB>(row, i, &shape, options))
+                .collect::<Result<_>>()?;
+            Ok(SelectResult::Objects(rows))
+        }
+        SelectFormat::Columns => {
+            let columns = shape
+                .keys
+                .names()
+                .zip(&shape.types)
+                .enumerate()
+                .map(|(i, ((table, name), column_type))| {
+                    let type_name = column_type_name::<DB>(first, i, column_type)?;
+                    Ok(ColumnInfo::new(table, name, &type_name))
+                })
+                .collect::<Result<_>>()?;
+            let rows = rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| row_values::<DB>(row, i, &shape, options))
+                .collect::<Result<_>>()?;
+            Ok(SelectResult::Columns { columns, rows })
+        }
+    }
+}
+
+/// Runs `query` on `executor` and sends the decoded rows to `chunks`, `chunk_size` rows at a time.
+///
+/// Returns the number of decoded rows, stopping early once the receiver of `chunks` is dropped.
+pub(crate) async fn select_chunked<'c, DB, E>(
+    executor: E,
+    query: &str,
+    values: BindValues,
+    options: &DecodeOptions,
+    chunk_size: usize,
+    chunks: mpsc::Sender<Vec<JsonRow>>,
+) -> Result<u64>
+where
+    DB: Driver,
+    E: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let chunk_size = chunk_size.max(1);
+    let (sql, values) =
+        values.into_positional(query, DB::PARAM_STYLE, DB::AT_VARIABLES, DB::BRACKET_QUOTES)?;
+    let query = bind_values::<DB>(DB::executed_query(&sql, options), values)?;
+
+    let mut rows = query.fetch(executor);
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let mut count = 0;
+    let mut shape = None;
+    while let Some(row) = poll_fn(|cx| rows.as_mut().poll_next(cx)).await {
+        let row = row?;
+        let shape = match shape {
+            Some(ref shape) => shape,
+            None => shape.insert(row_shape::<DB>(&sql, &row, options)?),
+        };
+        chunk.push(row_to_json::<DB>(&row, count as usize, shape, options)?);
+        count += 1;
+
+        if chunk.len() == chunk_size {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+            if chunks.send(full).await.is_err() {
+                return Ok(count);
+            }
+        }
+    }
+
+    if !chunk.is_empty() {
+        let _ = chunks.send(chunk).await;
+    }
+    Ok(count)
+}
+
+/// The keys and decoder types of the columns of a result set.
+pub(crate) struct ResultShape<DB: Driver> {
+    keys: RowShape,
+    types: Vec<DB::ColumnType>,
+}
+
+/// Computes the shape of the columns of `row`, the first row returned by `sql`.
+fn row_shape<DB: Driver>(
+    sql: &str,
+    row: &DB::Row,
+    options: &DecodeOptions,
+) -> Result<ResultShape<DB>> {
+    let columns = row.columns().iter().map(Column::name);
+    Ok(ResultShape {
+        keys: RowShape::new(sql, columns, options.duplicate_columns, DB::BRACKET_QUOTES)?,
+        types: row
+            .columns()
+            .iter()
+            .map(|column| DB::column_type(column, options))
+            .collect(),
+    })
+}
+
+/// Decodes every column of `row` with the driver's decoder, in order.
+///
+/// `index` is the position of `row` in the result set, reported by decode errors.
+fn row_values<DB>(
+    row: &DB::Row,
+    index: usize,
+    shape: &ResultShape<DB>,
+    options: &DecodeOptions,
+) -> Result<Vec<JsonValue>>
+where
+    DB: Driver,
+    usize: ColumnIndex<DB::Row>,
+{
+    row.columns()
+        .iter()
+        .zip(shape.keys.sources().iter().zip(&shape.types))
+        .enumerate()
+        .map(|(i, (column, (source, column_type)))| {
+            let value = row.try_get_raw(i)?;
+            match DB::to_json(value, column_type, *source, options) {
+                Err(Error::Decode { message, .. }) => Err(Error::Decode {
+                    column: column.name().to_string(),
+                    type_name: column_type_name::<DB>(row, i, column_type)?,
+                    row: index,
+                    message,
+                }),
+                result => result,
+            }
+        })
+        .collect()
+}
+
+/// Returns the type name of the column `i` of `row`, read as `column_type`.
+///
+/// SQLite expression columns have no type, the one of their value in `row` is reported.
+fn column_type_name<DB>(row: &DB::Row, i: usize, column_type: &DB::ColumnType) -> Result<String>
+where
+    DB: Driver,
+    usize: ColumnIndex<DB::Row>,
+{
+    if let Some(name) = DB::column_type_name(column_type) {
+        return Ok(name.to_string());
+    }
+    let column_type = row.column(i).type_info();
+    if !column_type.is_null() {
+        return Ok(DB::type_name(column_type).to_string());
+    }
+    Ok(DB::type_name(&row.try_get_raw(i)?.type_info()).to_string())
+}
+
+/// Decodes every column of `row` into an object keyed by `shape`.
+pub(crate) fn row_to_json<DB>(
+    row: &DB::Row,
+    index: usize,
+    shape: &ResultShape<DB>,
+    options: &DecodeOptions,
+) -> Result<JsonRow>
+where
+    DB: Driver,
+    usize: ColumnIndex<DB::Row>,
+{
+    Ok(shape
+        .keys
+        .row(row_values::<DB>(row, index, shape, options)?))
+}
+
+/// A connection pool of one of the enabled database drivers.
+///
+/// The driver is picked from the scheme of the connection url passed to `load`.
+#[derive(Clone)]
+pub(crate) enum DbPool {
+    #[cfg(feature = "sqlite")]
+    Sqlite(Pool<Sqlite>),
+    #[cfg(feature = "mysql")]
+    MySql(Pool<MySql>),
+    #[cfg(feature = "postgres")]
+    Postgres(Pool<Postgres>),
+    #[cfg(feature = "mssql")]
+    Mssql(Pool<Mssql>),
+}
+
+impl DbPool {
+    pub(crate) async fn connect<R: Runtime>(
+        conn_url: &str,
+        #[allow(unused_variables)] app: &AppHandle<R>,
+    ) -> Result<Self> {
+        let scheme = conn_url
+            .split_once(':')
+            .ok_or_else(|| Error::InvalidDbUrl(conn_url.to_string()))?
+            .0;
+
+        match scheme {
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                create_dir_all(app_path(app)).expect("Problem creating App directory!");
+
+                let conn_url = path_mapper(app_path(app), conn_url);
+                if !Sqlite::database_exists(&conn_url).await.unwrap_or(false) {
+                    Sqlite::create_database(&conn_url).await?;
+                }
+                Ok(Self::Sqlite(Pool::connect(&conn_url).await?))
+            }
+            #[cfg(feature = "mysql")]
+            "mysql" | "mariadb" => {
+                if !MySql::database_exists(conn_url).await.unwrap_or(false) {
+                    MySql::create_database(conn_url).await?;
+                }
+                Ok(Self::MySql(Pool::connect(conn_url).await?))
+            }
+            #[cfg(feature = "postgres")]
+            "postgres" | "postgresql" => {
+                if !Postgres::database_exists(conn_url).await.unwrap_or(false) {
+                    Postgres::create_database(conn_url).await?;
+                }
+                Ok(Self::Postgres(Pool::connect(conn_url).await?))
+            }
+            // sqlx can't create MSSQL databases, they have to exist beforehand.
+            #[cfg(feature = "mssql")]
+            "mssql" | "sqlserver" => Ok(Self::Mssql(Pool::connect(conn_url).await?)),
+            _ => Err(Error::InvalidDbUrl(conn_url.to_string())),
+        }
+    }
+
+    pub(crate) async fn migrate(&self, migrator: &Migrator) -> Result<()> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => Ok(migrator.run(pool).await?),
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => Ok(migrator.run(pool).await?),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => Ok(migrator.run(pool).await?),
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(_) => {
+                let _ = migrator;
+                Err(Error::MigrationsNotSupported("mssql"))
+            }
+        }
+    }
+
+    pub(crate) async fn close(&self) {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => pool.close().await,
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => pool.close().await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.close().await,
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(pool) => pool.close().await,
+        }
+    }
+
+    pub(crate) async fn begin(&self) -> Result<DbTransaction> {
+        Ok(match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => DbTransaction::Sqlite(pool.begin().await?),
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => DbTransaction::MySql(pool.begin().await?),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => DbTransaction::Postgres(pool.begin().await?),
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(pool) => DbTransaction::Mssql(pool.begin().await?),
+        })
+    }
+
+    pub(crate) async fn execute(
+        &self,
+        query: &str,
+        values: BindValues,
+    ) -> Result<(u64, LastInsertId)> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => execute(pool, query, values).await,
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => execute(pool, query, values).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => execute(pool, query, values).await,
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(pool) => execute(pool, query, values).await,
+        }
+    }
+
+    pub(crate) async fn select(
+        &self,
+        query: &str,
+        values: BindValues,
+        options: &DecodeOptions,
+        format: SelectFormat,
+    ) -> Result<SelectResult> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await?;
+                let json_columns = crate::decode::sqlite::json_columns(&mut conn, query).await?;
+                let options = options.with_json_columns(json_columns);
+                select(&mut *conn, query, values, &options, format).await
+            }
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => select(pool, query, values, options, format).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => select(pool, query, values, options, format).await,
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(pool) => {
+                let mut conn = pool.acquire().await?;
+                let (sql, values, options) =
+                    mssql_conversion(&mut conn, query, values, options).await?;
+                select(&mut *conn, &sql, values, &options, format).await
+            }
+        }
+    }
+
+    pub(crate) async fn select_chunked(
+        &self,
+        query: &str,
+        values: BindValues,
+        options: &DecodeOptions,
+        chunk_size: usize,
+        chunks: mpsc::Sender<Vec<JsonRow>>,
+    ) -> Result<u64> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await?;
+                let json_columns = crate::decode::sqlite::json_columns(&mut conn, query).await?;
+                let options = options.with_json_columns(json_columns);
+                select_chunked(&mut *conn, query, values, &options, chunk_size, chunks).await
+            }
+            #[cfg(feature = "mysql")]
+            DbPool::MySql(pool) => {
+                select_chunked(pool, query, values, options, chunk_size, chunks).await
+            }
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => {
+                select_chunked(pool, query, values, options, chunk_size, chunks).await
+            }
+            #[cfg(feature = "mssql")]
+            DbPool::Mssql(pool) => {
+                let mut conn = pool.acquire().await?;
+                let (sql, values, options) =
+                    mssql_conversion(&mut conn, query, values, options).await?;
+                select_chunked(&mut *conn, &sql, values, &options, chunk_size, chunks).await
+            }
+        }
+    }
+}
+
+/// An open transaction holding a connection of one of the enabled database drivers.
+///
+/// Dropping it without calling [`DbTransaction::commit`] rolls the transaction back.
+pub(crate) enum DbTransaction {
+    #[cfg(feature = "sqlite")]
+    Sqlite(Transaction<'static, Sqlite>),
+    #[cfg(feature = "mysql")]
+    MySql(Transaction<'static, MySql>),
+    #[cfg(feature = "postgres")]
+    Postgres(Transaction<'static, Postgres>),
+    #[cfg(feature = "mssql")]
+    Mssql(Transaction<'static, Mssql>),
+}
+
+impl DbTransaction {
+    pub(crate) async fn commit(self) -> Result<()> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => tx.commit().await?,
+            #[cfg(feature = "mysql")]
+            DbTransaction::MySql(tx) => tx.commit().await?,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => tx.commit().await?,
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(tx) => tx.commit().await?,
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn rollback(self) -> Result<()> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => tx.rollback().await?,
+            #[cfg(feature = "mysql")]
+            DbTransaction::MySql(tx) => tx.rollback().await?,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => tx.rollback().await?,
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(tx) => tx.rollback().await?,
+        }
+        Ok(())
+    }
+
+    /// Sets the savepoint `name`, making the following statements a nested transaction.
+    pub(crate) async fn savepoint(&mut self, name: &str) -> Result<()> {
+        let sql = match self {
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(_) => format!("SAVE TRANSACTION {name}"),
+            #[allow(unreachable_patterns)]
+            _ => format!("SAVEPOINT {name}"),
+        };
+        self.execute_unprepared(&sql).await
+    }
+
+    /// Keeps the changes made since the savepoint `name` as part of the enclosing transaction.
+    pub(crate) async fn release_savepoint(&mut self, name: &str) -> Result<()> {
+        match self {
+            // MSSQL savepoints can't be released, they end with the enclosing transaction.
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(_) => Ok(()),
+            #[allow(unreachable_patterns)]
+            _ => {
+                self.execute_unprepared(&format!("RELEASE SAVEPOINT {name}"))
+                    .await
+            }
+        }
+    }
+
+    /// Undoes the changes made since the savepoint `name` and discards it.
+    pub(crate) async fn rollback_to_savepoint(&mut self, name: &str) -> Result<()> {
+        match self {
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(_) => {
+                self.execute_unprepared(&format!("ROLLBACK TRANSACTION {name}"))
+                    .await
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                self.execute_unprepared(&format!("ROLLBACK TO SAVEPOINT {name}"))
+                    .await?;
+                self.execute_unprepared(&format!("RELEASE SAVEPOINT {name}"))
+                    .await
+            }
+        }
+    }
+
+    async fn execute_unprepared(&mut self, sql: &str) -> Result<()> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => {
+                tx.execute(sql).await?;
+            }
+            #[cfg(feature = "mysql")]
+            DbTransaction::MySql(tx) => {
+                tx.execute(sql).await?;
+            }
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => {
+                tx.execute(sql).await?;
+            }
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(tx) => {
+                tx.execute(sql).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn execute(
+        &mut self,
+        query: &str,
+        values: BindValues,
+    ) -> Result<(u64, LastInsertId)> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => execute(&mut **tx, query, values).await,
+            #[cfg(feature = "mysql")]
+            DbTransaction::MySql(tx) => execute(&mut **tx, query, values).await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => execute(&mut **tx, query, values).await,
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(tx) => execute(&mut **tx, query, values).await,
+        }
+    }
+
+    pub(crate) async fn select(
+        &mut self,
+        query: &str,
+        values: BindValues,
+        options: &DecodeOptions,
+        format: SelectFormat,
+    ) -> Result<SelectResult> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => {
+                let json_columns = crate::decode::sqlite::json_columns(tx, query).await?;
+                let options = options.with_json_columns(json_columns);
+                select(&mut **tx, query, values, &options, format).await
+            }
+            #[cfg(feature = "mysql")]
+            DbTransaction::MySql(tx) => select(&mut **tx, query, values, options, format).await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => select(&mut **tx, query, values, options, format).await,
+            #[cfg(feature = "mssql")]
+            DbTransaction::Mssql(tx) => {
+                let (sql, values, options) = mssql_conversion(tx, query, values, options).await?;
+                select(&mut **tx, &sql, values, &options, format).await
+            }
+        }
+    }
+}
+
+/// A single statement of an `execute_batch` call.
+#[derive(Deserialize)]
+pub(crate) struct BatchStatement {
+    query: String,
+    #[serde(default)]
+    values: BindValues,
+}
+
+/// The statements of an `execute_batch` call, either listed one by one
+/// or as one query executed once per row of values.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Batch {
+    Statements(Vec<BatchStatement>),
+    Rows {
+        query: String,
+        rows: Vec<BindValues>,
+    },
+}
+
+impl Batch {
+    /// Encodes the values of every statement, see [`BindValues::encode`].
+    pub(crate) fn encode(self, codecs: &TypeCodecs) -> Result<Self> {
+        Ok(match self {
+            Batch::Statements(statements) => Batch::Statements(
+                statements
+                    .into_iter()
+                    .map(|BatchStatement { query, values }| {
+                        Ok(BatchStatement {
+                            query,
+                            values: values.encode(codecs)?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            Batch::Rows { query, rows } => Batch::Rows {
+                query,
+                rows: rows
+                    .into_iter()
+                    .map(|values| values.encode(codecs))
+                    .collect::<Result<_>>()?,
+            },
+        })
+    }
+
+    fn into_statements(self) -> Vec<BatchStatement> {
+        match self {
+            Batch::Statements(statements) => statements,
+            Batch::Rows { query, rows } => rows
+                .into_iter()
+                .map(|values| BatchStatement {
+                    query: query.clone(),
+                    values,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The outcome of a single statement of a batch.
+#[derive(Serialize)]
+#[serde(untagged, rename_all_fields = "camelCase")]
+pub(crate) enum BatchResult {
+    Executed {
+        rows_affected: u64,
+        last_insert_id: LastInsertId,
+    },
+    Failed {
+        error: Error,
+    },
+}
+
+impl DbTransaction {
+    /// Executes every statement of `batch` in order and returns their outcomes.
+    ///
+    /// Without `continue_on_error` the first failing statement aborts the batch with
+    /// [`Error::BatchStatement`], leaving it to the caller to roll back. Otherwise every
+    /// statement runs inside its own savepoint so a failure only undoes that statement.
+    pub(crate) async fn execute_batch(
+        &mut self,
+        batch: Batch,
+        continue_on_error: bool,
+    ) -> Result<Vec<BatchResult>> {
+        const SAVEPOINT: &str = "tauri_sql_batch_statement";
+
+        let statements = batch.into_statements();
+        let mut results = Vec::with_capacity(statements.len());
+        for (index, statement) in statements.into_iter().enumerate() {
+            if !continue_on_error {
+                let (rows_affected, last_insert_id) = self
+                    .execute(&statement.query, statement.values)
+                    .await
+                    .map_err(|e| Error::BatchStatement {
+                        index,
+                        source: Box::new(e),
+                    })?;
+                results.push(BatchResult::Executed {
+                    rows_affected,
+                    last_insert_id,
+                });
+                continue;
+            }
+
+            self.savepoint(SAVEPOINT).await?;
+            match self.execute(&statement.query, statement.values).await {
+                Ok((rows_affected, last_insert_id)) => {
+                    self.release_savepoint(SAVEPOINT).await?;
+                    results.push(BatchResult::Executed {
+                        rows_affected,
+                        last_insert_id,
+                    });
+                }
+                Err(error) => {
+                    self.rollback_to_savepoint(SAVEPOINT).await?;
+                    results.push(BatchResult::Failed { error });
+                }
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn app_path<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
+    app.path().app_config_dir().expect("No App path was found!")
+}
+
+#[cfg(feature = "sqlite")]
+fn path_mapper(mut app_path: PathBuf, connection_string: &str) -> String {
+    app_path.push(
+        connection_string
+            .split_once(':')
+            .expect("Couldn't parse the connection string for DB!")
+            .1,
+    );
+
+    format!(
+        "sqlite:{}",
+        app_path
+            .to_str()
+            .expect("Problem creating fully qualified path to Database file!")
+    )
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::*;
+
+    fn bind(items: Vec<BindValue>) -> Result<()> {
+        bind_array(sqlx::query::<Postgres>("SELECT $1"), items).map(drop)
+    }
+
+    #[test]
+    fn arrays_bind_one_element_type() {
+        bind(vec![
+            BindValue::Null,
+            BindValue::Int(1),
+            BindValue::Float(1.5),
+        ])
+        .unwrap();
+        bind(vec![BindValue::Null]).unwrap();
+
+        let error = bind(vec![BindValue::Int(1), BindValue::Text("a".into())]).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("array elements must have the same type"));
+    }
+
+    #[test]
+    fn nested_arrays_are_rejected() {
+        let error = bind(vec![BindValue::Array(vec![BindValue::Int(1)])]).unwrap_err();
+        assert!(error.to_string().contains("arrays can't be nested"));
+    }
+}